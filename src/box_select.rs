@@ -1,12 +1,16 @@
+use bevy::camera::primitives::Aabb;
+use bevy::ecs::query::QueryFilter;
+use bevy::math::bounding::Aabb3d;
 use bevy::light::{NotShadowCaster, NotShadowReceiver};
 use bevy::ecs::spawn::SpawnWith;
 use bevy::prelude::*;
-use bevy_enhanced_input::prelude::Cancel;
+use bevy_enhanced_input::prelude::{Cancel, Complete, Press};
 use bevy_enhanced_input::prelude::*;
 use bevy::color::palettes::tailwind::*;
 use libm::fabsf;
 
-use crate::world_pos::WorldPos;
+use crate::selection::{PASS_THROUGH, ToolKey};
+use crate::world_pos::{WorldPos, EditorCamera, UiHover};
 
 
 pub struct PGEditorBoxSelectPlugin;
@@ -14,30 +18,135 @@ pub struct PGEditorBoxSelectPlugin;
 impl Plugin for PGEditorBoxSelectPlugin {
     fn build(&self, app: &mut App) {
         app
+        .init_resource::<BoxSelectSettings>()
+        .init_resource::<BoxSelectState>()
         .add_input_context::<BoxSelectController>()
         .add_observer(start_boxselect)
         .add_observer(update_boxselect)
         .add_observer(end_boxselect)             
+        .add_observer(complete_boxselect)
+        .add_observer(abort_boxselect)
+        .add_observer(bind_boxselect)
+        .add_systems(Startup, setup_boxselect_assets)
+        .add_systems(Update, 
+            (
+                update_boxselect_material.run_if(resource_changed::<BoxSelectSettings>),
+                preview_boxselect.run_if(box_select_changed),
+                draw_boxselect_outline.run_if(any_with_component::<BoxSelect>)
+            )
+        )
         ;
     }
 }
 
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum BoxSelectStyle {
+    #[default]
+    Fill,
+    Outline
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct BoxSelectSettings {
+    pub key:             Binding,
+    pub button:          Binding,
+    pub cancel:          Binding,
+    pub color:           Color,
+    pub style:           BoxSelectStyle,
+    pub height:          f32,
+    pub min_drag:        f32,   // Smaller boxes end without BoxSelectFinal
+    pub align_to_camera: bool,
+    pub policy:          SelectPolicy,
+    pub record_changes:  bool   // Record selection changes for undo when Changes exists
+}
+impl Default for BoxSelectSettings {
+    fn default() -> Self {
+        Self {
+            key:             KeyCode::KeyB.into(),
+            button:          MouseButton::Right.into(),
+            cancel:          KeyCode::Escape.into(),
+            color:           Color::from(ORANGE_600).with_alpha(0.4),
+            style:           BoxSelectStyle::Fill,
+            height:          1.0,
+            min_drag:        0.1,
+            align_to_camera: true,
+            policy:          SelectPolicy::Origin,
+            record_changes:  true
+        }
+    }
+}
+
+
+// Shared by every box select, the cuboid is unit sized and scaled by the Transform
+#[derive(Resource)]
+pub struct BoxSelectAssets {
+    pub mesh:     Handle<Mesh>,
+    pub material: Handle<StandardMaterial>
+}
+
+fn setup_boxselect_assets(
+    mut commands:   Commands,
+    mut meshes:     ResMut<Assets<Mesh>>,
+    mut materials:  ResMut<Assets<StandardMaterial>>,
+    settings:       Res<BoxSelectSettings>
+){
+    commands.insert_resource(BoxSelectAssets{
+        mesh:     meshes.add(Cuboid::default()),
+        material: materials.add(settings.color)
+    });
+}
+
+fn update_boxselect_material(
+    mut materials:  ResMut<Assets<StandardMaterial>>,
+    settings:       Res<BoxSelectSettings>,
+    assets:         Option<Res<BoxSelectAssets>>
+){
+    let Some(assets) = assets else {return;};
+    if let Some(material) = materials.get_mut(&assets.material) {
+        material.base_color = settings.color;
+        material.alpha_mode = if settings.color.alpha() < 1.0 {AlphaMode::Blend} else {AlphaMode::Opaque};
+    }
+}
+
+
 #[derive(Component, Reflect)]
 pub struct BoxSelectController;
 
+// Bound from the BoxSelectSettings resource once spawned
 pub fn box_select_controller() -> impl Bundle {
+    (BoxSelectController, BoxSelectBindFromSettings)
+}
+
+#[derive(Component)]
+struct BoxSelectBindFromSettings;
+
+fn bind_boxselect(
+    trigger:        On<Add, BoxSelectBindFromSettings>,
+    mut commands:   Commands,
+    settings:       Res<BoxSelectSettings>
+){
+    commands
+        .entity(trigger.event().entity)
+        .remove::<BoxSelectBindFromSettings>()
+        .insert(box_select_controller_with(&settings));
+}
+
+// Bindings are read once, respawn the controller after changing them
+pub fn box_select_controller_with(settings: &BoxSelectSettings) -> impl Bundle + use<> {
+    let (key, button, cancel) = (settings.key, settings.button, settings.cancel);
     return (
         BoxSelectController,
         Actions::<BoxSelectController>::spawn(
-            SpawnWith(|context: &mut ActionSpawner<_>| {
+            SpawnWith(move |context: &mut ActionSpawner<_>| {
             let member1 = context
-                .spawn((Action::<BoxSelectUpdate1>::new(), Down::default(), bindings![KeyCode::KeyB]))
+                .spawn((Action::<BoxSelectUpdate1>::new(), Down::default(), PASS_THROUGH, ToolKey, bindings![key]))
                 .id();
             let member2 = context
-                .spawn((Action::<BoxSelectUpdate2>::new(), Down::default(), bindings![MouseButton::Right]))
+                .spawn((Action::<BoxSelectUpdate2>::new(), Down::default(), PASS_THROUGH, bindings![button]))
                 .id();
             context.spawn((Action::<BoxSelectUpdate>::new(), Chord::new([member1, member2])));
+            context.spawn((Action::<BoxSelectAbort>::new(), Press::default(), PASS_THROUGH, bindings![cancel]));
 
             })) 
         );
@@ -55,32 +164,155 @@ struct BoxSelectUpdate2;
 #[action_output(bool)]
 struct BoxSelectUpdate;
 
-#[derive(Event)]
+#[derive(InputAction)]
+#[action_output(bool)]
+struct BoxSelectAbort;
+
+
+// Cancelled lasts until the chord is released, so the drag doesn't restart
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoxSelectState {
+    #[default]
+    Idle,
+    Dragging(Entity),
+    Cancelled
+}
+impl BoxSelectState {
+    pub fn is_dragging(&self) -> bool {
+        matches!(self, BoxSelectState::Dragging(_))
+    }
+}
+
+// How entity bounds are tested against the selection box
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum SelectPolicy {
+    #[default]
+    Origin,     // Entity origin inside the box
+    Intersect,  // Any part of the entity bounds inside the box
+    Contain     // Whole entity bounds inside the box
+}
+
+// Box is oriented by `rotation`, aabb is stored in the box's own frame
+#[derive(Event, Clone, Copy, Debug)]
 pub struct BoxSelectFinal{
-    aabb: AABB
+    aabb:     AABB,
+    rotation: Quat
 }
 impl BoxSelectFinal {
+    pub fn aabb(&self) -> &AABB {
+        &self.aabb
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.rotation
+    }
+
+    // World axis aligned bounds enclosing the oriented box
+    pub fn world_aabb(&self) -> AABB {
+        let bounds = Aabb::from_min_max(self.aabb.min(), self.aabb.max());
+        AABB::from_bounds(&GlobalTransform::from_rotation(self.rotation), &bounds)
+    }
+
+    pub fn min(&self) -> Vec3 {
+        self.world_aabb().min()
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.world_aabb().max()
+    }
+
+    pub fn to_local(&self, loc: Vec3) -> Vec3 {
+        self.rotation.inverse() * loc
+    }
+
+    // Terrain heights swept by the box while dragging
+    pub fn y_range(&self) -> (f32, f32) {
+        self.aabb.y_range()
+    }
+
     pub fn has_point(&self, loc: Vec2) -> bool {
-        self.aabb.has_point(loc)
+        self.aabb.has_point(self.to_local(Vec3::new(loc.x, 0.0, loc.y)).xz())
+    }
+
+    // Entity bounds are tested in the box's frame, so these are oriented box tests
+    pub fn intersects_aabb(&self, transform: &GlobalTransform, aabb: &Aabb) -> bool {
+        self.aabb.intersects(&AABB::from_bounds(&self.local_transform(transform), aabb))
+    }
+
+    pub fn contains_aabb(&self, transform: &GlobalTransform, aabb: &Aabb) -> bool {
+        self.aabb.contains(&AABB::from_bounds(&self.local_transform(transform), aabb))
+    }
+
+    fn local_transform(&self, transform: &GlobalTransform) -> GlobalTransform {
+        GlobalTransform::from_rotation(self.rotation.inverse()) * *transform
+    }
+
+    // Entities without bounds fall back to their origin
+    pub fn selects(&self, policy: SelectPolicy, transform: &GlobalTransform, aabb: Option<&Aabb>) -> bool {
+        match (policy, aabb) {
+            (SelectPolicy::Intersect, Some(aabb)) => self.intersects_aabb(transform, aabb),
+            (SelectPolicy::Contain, Some(aabb))   => self.contains_aabb(transform, aabb),
+            _ => self.has_point(transform.translation().xz())
+        }
+    }
+
+    // Entities whose origin is inside the box
+    pub fn query_entities<F: QueryFilter>(&self, query: &Query<(Entity, &GlobalTransform), F>) -> Vec<Entity> {
+        query
+            .iter()
+            .filter(|(_, transform)| self.has_point(transform.translation().xz()))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    pub fn query_bounds<F: QueryFilter>(
+        &self, 
+        policy: SelectPolicy, 
+        query:  &Query<(Entity, &GlobalTransform, Option<&Aabb>), F>
+    ) -> Vec<Entity> {
+        query
+            .iter()
+            .filter(|(_, transform, aabb)| self.selects(policy, transform, *aabb))
+            .map(|(entity, _, _)| entity)
+            .collect()
     }
 }
 
 
+// Triggered every frame the box changes while dragging
+#[derive(Event, Clone, Copy, Debug, Deref)]
+pub struct BoxSelectPreview(pub BoxSelectFinal);
+
+
 #[derive(Component, Debug)]
 pub struct BoxSelect {
     pub start:  Vec3,
     pub loc:    Vec3,
-    pub dims:   Vec2
+    pub dims:   Vec2,
+    pub min_y:  f32,
+    pub max_y:  f32,
+    pub yaw:    f32
 }
 
 impl BoxSelect {
-    fn new(loc: &Vec3) -> Self {
+    fn new(loc: &Vec3, yaw: f32) -> Self {
         BoxSelect {
             start: *loc,
             loc: *loc,
-            dims: Vec2::ZERO
+            dims: Vec2::ZERO,
+            min_y: loc.y,
+            max_y: loc.y,
+            yaw
         }
     }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw)
+    }
+
+    pub fn to_final(&self) -> BoxSelectFinal {
+        BoxSelectFinal{aabb: AABB::from_box_select(self), rotation: self.rotation()}
+    }
 }
 
 impl Default for BoxSelect {
@@ -88,61 +320,173 @@ impl Default for BoxSelect {
         BoxSelect {
             start: Vec3::ZERO,
             loc: Vec3::ZERO,
-            dims: Vec2::ZERO
+            dims: Vec2::ZERO,
+            min_y: 0.0,
+            max_y: 0.0,
+            yaw: 0.0
         }
     }
 }
 
 
-fn start_boxselect(
-    _trigger:       On<Start<BoxSelectUpdate>>,
-    mut commands:   Commands,
-    mut meshes:     ResMut<Assets<Mesh>>,
-    mut materials:  ResMut<Assets<StandardMaterial>>,
-    input_data:     Res<WorldPos>
-){
-    let Some(world_pos) = input_data.get() else {return;};
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::default())),
-        MeshMaterial3d(materials.add(Color::from(ORANGE_600).with_alpha(0.4))),
-        Transform::from_translation(world_pos),
-        BoxSelect::new(&world_pos),
+fn spawn_boxselect(
+    commands:   &mut Commands,
+    assets:     &BoxSelectAssets,
+    settings:   &BoxSelectSettings,
+    world_pos:  Vec3,
+    yaw:        f32
+) -> Entity {
+    let box_select = BoxSelect::new(&world_pos, yaw);
+    let transform = Transform::from_translation(world_pos)
+        .with_rotation(box_select.rotation())
+        .with_scale(Vec3::new(0.0, settings.height, 0.0));
+    let mut entity = commands.spawn((
+        transform,
+        box_select,
         NotShadowCaster,
         NotShadowReceiver
     ));
+    if settings.style == BoxSelectStyle::Fill {
+        entity.insert((
+            Mesh3d(assets.mesh.clone()),
+            MeshMaterial3d(assets.material.clone())
+        ));
+    }
+    entity.id()
 }
 
+fn start_boxselect(
+    _trigger:       On<Start<BoxSelectUpdate>>,
+    mut commands:   Commands,
+    mut state:      ResMut<BoxSelectState>,
+    ui_hover:       UiHover,
+    boxes:          Query<Entity, With<BoxSelect>>
+){
+    // Leftovers from a drag that never ended
+    for bs_entity in boxes.iter(){
+        commands.entity(bs_entity).despawn();
+    }
+    // Pressed over the UI, the drag is ignored until released
+    *state = if ui_hover.any() {BoxSelectState::Cancelled} else {BoxSelectState::Idle};
+}
 
+// Box is spawned on the first frame with a WorldPos, so starting off the terrain still works
 fn update_boxselect(
-    _trigger:   On<Fire<BoxSelectUpdate>>,
-    input_data: Res<WorldPos>,
-    query:      Single<(&mut Transform, &mut BoxSelect)>
+    _trigger:       On<Fire<BoxSelectUpdate>>,
+    mut commands:   Commands,
+    assets:         Res<BoxSelectAssets>,
+    mut state:      ResMut<BoxSelectState>,
+    input_data:     Res<WorldPos>,
+    settings:       Res<BoxSelectSettings>,
+    camera:         Query<&GlobalTransform, With<EditorCamera>>,
+    mut query:      Query<(&mut Transform, &mut BoxSelect)>
 ){
     let Some(world_pos) = input_data.get() else {return;};
-    let (mut transform, mut box_select) = query.into_inner();
+    let bs_entity = match *state {
+        BoxSelectState::Cancelled => return,
+        BoxSelectState::Idle => {
+            let yaw = match settings.align_to_camera {
+                true  => camera.single().map(EditorCamera::yaw).unwrap_or(0.0),
+                false => 0.0
+            };
+            let bs_entity = spawn_boxselect(&mut commands, &assets, &settings, world_pos, yaw);
+            *state = BoxSelectState::Dragging(bs_entity);
+            return;
+        }
+        BoxSelectState::Dragging(bs_entity) => bs_entity
+    };
+    let Ok((mut transform, mut box_select)) = query.get_mut(bs_entity) else {
+        *state = BoxSelectState::Idle;
+        return;
+    };
 
-    let new_x = (world_pos.x + box_select.start.x) / 2.0;
-    let new_z = (world_pos.z + box_select.start.z) / 2.0;
-    let dim_x = fabsf(world_pos.x - box_select.start.x);
-    let dim_z = fabsf(world_pos.z - box_select.start.z);
+    // Work in the box's own frame so it can follow the camera yaw
+    let rotation = box_select.rotation();
+    let local_start = rotation.inverse() * box_select.start;
+    let local_pos = rotation.inverse() * world_pos;
+    let new_x = (local_pos.x + local_start.x) / 2.0;
+    let new_z = (local_pos.z + local_start.z) / 2.0;
+    let dim_x = fabsf(local_pos.x - local_start.x);
+    let dim_z = fabsf(local_pos.z - local_start.z);
     let max_y = world_pos.y.max(box_select.start.y) + 0.1;  
     let dims = Vec2::new(dim_x, dim_z);
-    let loc = Vec3A::new(new_x, max_y, new_z);
+    let loc = Vec3A::from(rotation * Vec3::new(new_x, max_y, new_z));
+    box_select.min_y = box_select.min_y.min(world_pos.y);
+    box_select.max_y = box_select.max_y.max(world_pos.y);
     box_select.loc = loc.into();
     box_select.dims = dims; 
     transform.translation = loc.into();
-    transform.scale = Vec3::new(dims.x, 1.0, dims.y);
+    transform.rotation = rotation;
+    transform.scale = Vec3::new(dims.x, settings.height, dims.y);
+}
+
+fn finish_boxselect(
+    commands:   &mut Commands,
+    state:      &mut BoxSelectState,
+    settings:   &BoxSelectSettings,
+    query:      &Query<&BoxSelect>
+){
+    if let BoxSelectState::Dragging(bs_entity) = *state {
+        if let Ok(box_select) = query.get(bs_entity) && box_select.dims.max_element() >= settings.min_drag {
+            commands.trigger(box_select.to_final());
+        }
+        commands.entity(bs_entity).try_despawn();
+    }
+    *state = BoxSelectState::Idle;
 }
 
 fn end_boxselect(
     _trigger:       On<Cancel<BoxSelectUpdate>>,
     mut commands:   Commands,
-    query:          Single<(Entity, &BoxSelect)>
+    mut state:      ResMut<BoxSelectState>,
+    settings:       Res<BoxSelectSettings>,
+    query:          Query<&BoxSelect>
+){
+    finish_boxselect(&mut commands, &mut state, &settings, &query);
+}
+
+fn complete_boxselect(
+    _trigger:       On<Complete<BoxSelectUpdate>>,
+    mut commands:   Commands,
+    mut state:      ResMut<BoxSelectState>,
+    settings:       Res<BoxSelectSettings>,
+    query:          Query<&BoxSelect>
+){
+    finish_boxselect(&mut commands, &mut state, &settings, &query);
+}
+
+// Drops the box without BoxSelectFinal
+fn abort_boxselect(
+    _trigger:       On<Start<BoxSelectAbort>>,
+    mut commands:   Commands,
+    mut state:      ResMut<BoxSelectState>
 ){
-    let (bs_entity, box_select) = query.into_inner();
-    let aabb = AABB::from_loc_dims(box_select.loc.xz(), box_select.dims);
-    commands.trigger(BoxSelectFinal{aabb: aabb});
-    commands.entity(bs_entity).despawn();
+    if let BoxSelectState::Dragging(bs_entity) = *state {
+        commands.entity(bs_entity).try_despawn();
+        *state = BoxSelectState::Cancelled;
+    }
+}
+
+fn preview_boxselect(
+    mut commands:   Commands,
+    query:          Query<&BoxSelect, Changed<BoxSelect>>
+){
+    for box_select in query.iter(){
+        commands.trigger(BoxSelectPreview(box_select.to_final()));
+    }
+}
+
+fn draw_boxselect_outline(
+    mut gizmos: Gizmos,
+    settings:   Res<BoxSelectSettings>,
+    query:      Query<&Transform, With<BoxSelect>>
+){
+    if settings.style != BoxSelectStyle::Outline {
+        return;
+    }
+    for transform in query.iter(){
+        gizmos.cuboid(*transform, settings.color.with_alpha(1.0));
+    }
 }
 
 pub fn box_select_changed(
@@ -151,12 +495,15 @@ pub fn box_select_changed(
     !query.is_empty()
 }
 
+// Selection is a vertical column over XZ, Y only records the swept heights
 #[derive(Clone, Copy, Debug, PartialEq)]
-struct AABB {
-    min_x: f32,
-    max_x: f32,
-    min_z: f32,
-    max_z: f32,
+pub struct AABB {
+    pub min_x: f32,
+    pub max_x: f32,
+    pub min_y: f32,
+    pub max_y: f32,
+    pub min_z: f32,
+    pub max_z: f32,
 }
 
 impl Default for AABB {
@@ -164,6 +511,8 @@ impl Default for AABB {
         return AABB{
             min_x: 0.0, 
             max_x: 0.0,
+            min_y: 0.0, 
+            max_y: 0.0,
             min_z: 0.0, 
             max_z: 0.0
         };
@@ -177,10 +526,82 @@ impl AABB {
             max_x: loc.x + dim.x / 2.0,
             min_z: loc.y - dim.y / 2.0,
             max_z: loc.y + dim.y / 2.0,
+            ..default()
+        }
+    }
+
+    // In the box's own frame, see BoxSelect::rotation
+    pub fn from_box_select(box_select: &BoxSelect) -> AABB {
+        let local_loc = box_select.rotation().inverse() * box_select.loc;
+        AABB {
+            min_y: box_select.min_y,
+            max_y: box_select.max_y,
+            ..AABB::from_loc_dims(local_loc.xz(), box_select.dims)
+        }
+    }
+
+    // World space XZ bounds of a local Aabb
+    pub fn from_bounds(transform: &GlobalTransform, aabb: &Aabb) -> AABB {
+        let affine = transform.affine();
+        let (min, max) = (aabb.min(), aabb.max());
+        let mut bounds = AABB{
+            min_x: f32::MAX,
+            max_x: f32::MIN,
+            min_y: f32::MAX,
+            max_y: f32::MIN,
+            min_z: f32::MAX,
+            max_z: f32::MIN
+        };
+        for i in 0..8 {
+            let corner = Vec3A::new(
+                if i & 1 == 0 {min.x} else {max.x},
+                if i & 2 == 0 {min.y} else {max.y},
+                if i & 4 == 0 {min.z} else {max.z}
+            );
+            let world = affine.transform_point3a(corner);
+            bounds.min_x = bounds.min_x.min(world.x);
+            bounds.max_x = bounds.max_x.max(world.x);
+            bounds.min_y = bounds.min_y.min(world.y);
+            bounds.max_y = bounds.max_y.max(world.y);
+            bounds.min_z = bounds.min_z.min(world.z);
+            bounds.max_z = bounds.max_z.max(world.z);
+        }
+        bounds
+    }
+
+    pub fn min(&self) -> Vec3 {
+        Vec3::new(self.min_x, self.min_y, self.min_z)
+    }
+
+    pub fn max(&self) -> Vec3 {
+        Vec3::new(self.max_x, self.max_y, self.max_z)
+    }
+
+    pub fn y_range(&self) -> (f32, f32) {
+        (self.min_y, self.max_y)
+    }
+
+    // XZ footprint, Z is mapped to Rect's Y
+    pub fn to_rect(&self) -> Rect {
+        Rect::new(self.min_x, self.min_z, self.max_x, self.max_z)
+    }
+
+    pub fn to_aabb3d(&self) -> Aabb3d {
+        Aabb3d {
+            min: self.min().into(),
+            max: self.max().into()
         }
     }
 
     pub fn has_point(&self, loc: Vec2) -> bool {
         loc.x >= self.min_x && loc.x <= self.max_x && loc.y >= self.min_z && loc.y <= self.max_z
     }
+
+    pub fn intersects(&self, other: &AABB) -> bool {
+        self.min_x <= other.max_x && self.max_x >= other.min_x && self.min_z <= other.max_z && self.max_z >= other.min_z
+    }
+
+    pub fn contains(&self, other: &AABB) -> bool {
+        other.min_x >= self.min_x && other.max_x <= self.max_x && other.min_z >= self.min_z && other.max_z <= self.max_z
+    }
 }
\ No newline at end of file
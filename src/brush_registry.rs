@@ -0,0 +1,155 @@
+use bevy::ecs::spawn::SpawnWith;
+use bevy::prelude::*;
+use bevy_enhanced_input::prelude::Press;
+use bevy_enhanced_input::prelude::*;
+
+use crate::brushes::{BrushSettings, BrushType};
+
+const SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3,
+    KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6,
+    KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9
+];
+
+
+pub struct PGEditorBrushRegistryPlugin;
+
+impl Plugin for PGEditorBrushRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<BrushRegistry>()
+        .add_message::<SetActiveBrush>()
+        .add_input_context::<BrushHotkeysController>()
+        .add_observer(select_brush_slot)
+        .add_systems(Update, set_active_brush.run_if(on_message::<SetActiveBrush>))
+        ;
+    }
+}
+
+
+// Brush with the settings it had when another brush was activated
+#[derive(Clone)]
+pub struct RegisteredBrush {
+    pub name:     String,
+    pub radius:   f32,
+    pub strength: f32,
+    pub typ:      Box<dyn BrushType>
+}
+
+// Named brushes in toolbar order, slot i is activated by number key i + 1
+#[derive(Resource, Default)]
+pub struct BrushRegistry {
+    brushes: Vec<RegisteredBrush>,
+    active:  Option<usize>
+}
+
+impl BrushRegistry {
+    pub fn register(&mut self, name: impl Into<String>, typ: impl BrushType) -> usize {
+        let defaults = BrushSettings::default();
+        self.brushes.push(RegisteredBrush{
+            name:     name.into(),
+            radius:   defaults.radius,
+            strength: defaults.strength,
+            typ:      Box::new(typ)
+        });
+        self.brushes.len() - 1
+    }
+
+    pub fn with_brush(mut self, name: impl Into<String>, typ: impl BrushType) -> Self {
+        self.register(name, typ);
+        self
+    }
+
+    pub fn get(&self, index: usize) -> Option<&RegisteredBrush> {
+        self.brushes.get(index)
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.brushes.iter().position(|brush| brush.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredBrush> {
+        self.brushes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.brushes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.brushes.is_empty()
+    }
+
+    pub fn active(&self) -> Option<usize> {
+        self.active
+    }
+
+    // Stores the current settings in the active brush and loads the new one into them
+    pub fn activate(&mut self, index: usize, brush_settings: &mut BrushSettings) -> bool {
+        if index >= self.brushes.len() || self.active == Some(index) {
+            return false;
+        }
+        if let Some(active) = self.active.and_then(|active| self.brushes.get_mut(active)) {
+            active.radius = brush_settings.radius;
+            active.strength = brush_settings.strength;
+            active.typ = brush_settings.typ.clone();
+        }
+        let brush = &self.brushes[index];
+        brush_settings.radius = brush.radius;
+        brush_settings.strength = brush.strength;
+        brush_settings.typ = brush.typ.clone();
+        self.active = Some(index);
+        true
+    }
+}
+
+
+// Index into the BrushRegistry
+#[derive(Message, Clone, Copy, Debug)]
+pub struct SetActiveBrush(pub usize);
+
+
+#[derive(Component, Reflect)]
+pub struct BrushHotkeysController;
+
+// Registry slot of a SelectBrushSlot action
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BrushSlot(pub usize);
+
+pub fn brush_hotkeys_controller() -> impl Bundle {
+    return (
+        BrushHotkeysController,
+        Actions::<BrushHotkeysController>::spawn(
+            SpawnWith(|context: &mut ActionSpawner<_>| {
+            for (slot, key) in SLOT_KEYS.into_iter().enumerate() {
+                context.spawn((Action::<SelectBrushSlot>::new(), Press::default(), BrushSlot(slot), bindings![key]));
+            }
+
+            }))
+        );
+}
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct SelectBrushSlot;
+
+
+fn select_brush_slot(
+    trigger:    On<Start<SelectBrushSlot>>,
+    slots:      Query<&BrushSlot>,
+    mut writer: MessageWriter<SetActiveBrush>
+){
+    let Ok(slot) = slots.get(trigger.event().action) else {return;};
+    writer.write(SetActiveBrush(slot.0));
+}
+
+fn set_active_brush(
+    mut reader:         MessageReader<SetActiveBrush>,
+    mut registry:       ResMut<BrushRegistry>,
+    brush_settings:     Option<ResMut<BrushSettings>>
+){
+    let Some(mut brush_settings) = brush_settings else {return;};
+    for message in reader.read(){
+        registry.activate(message.0, &mut brush_settings);
+    }
+}
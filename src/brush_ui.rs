@@ -0,0 +1,380 @@
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+use bevy::color::palettes::tailwind::*;
+
+use crate::brushes::{BrushParam, BrushParamValue, BrushSelectController, BrushSettings};
+
+const PANEL_WIDTH: f32 = 200.0;
+const FONT_SIZE: f32 = 12.0;
+
+
+// Panel listing BrushSettings and the parameters of the active BrushType,
+// shown while a BrushSelectController exists
+pub struct PGEditorBrushUiPlugin;
+
+impl Plugin for PGEditorBrushUiPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_systems(Startup, spawn_brush_panel)
+        .add_systems(Update,
+            (
+                show_brush_panel,
+                (
+                    drag_brush_slider,
+                    click_brush_toggle,
+                    click_brush_dropdown,
+                    click_brush_option
+                ).run_if(resource_exists::<BrushSettings>),
+                update_brush_panel.run_if(resource_exists_and_changed::<BrushSettings>)
+            ).chain()
+        )
+        ;
+    }
+}
+
+
+// Parameters the panel was built for, rebuilt when they change shape
+#[derive(Component, Default)]
+pub struct BrushPanel {
+    params: Vec<(ParamSource, BrushParam)>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParamSource {
+    Settings,   // BrushSettings::params
+    Brush       // BrushType::params of BrushSettings::typ
+}
+
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+struct ParamWidget {
+    source: ParamSource,
+    name:   &'static str
+}
+
+#[derive(Component)]
+struct ParamLabel;
+
+#[derive(Component)]
+struct SliderTrack {
+    min: f32,
+    max: f32
+}
+
+#[derive(Component)]
+struct SliderFill {
+    min: f32,
+    max: f32
+}
+
+#[derive(Component)]
+struct ToggleButton;
+
+#[derive(Component)]
+struct DropdownButton;
+
+#[derive(Component)]
+struct DropdownText;
+
+#[derive(Component)]
+struct DropdownOptions;
+
+#[derive(Component)]
+struct DropdownOption(usize);
+
+
+fn panel_params(brush_settings: &BrushSettings) -> Vec<(ParamSource, BrushParam)> {
+    brush_settings.params().into_iter().map(|param| (ParamSource::Settings, param))
+        .chain(brush_settings.typ.params().into_iter().map(|param| (ParamSource::Brush, param)))
+        .collect()
+}
+
+fn find_param(brush_settings: &BrushSettings, widget: &ParamWidget) -> Option<BrushParam> {
+    panel_params(brush_settings)
+        .into_iter()
+        .find(|(source, param)| *source == widget.source && param.name() == widget.name)
+        .map(|(_, param)| param)
+}
+
+fn set_param(brush_settings: &mut BrushSettings, widget: &ParamWidget, value: BrushParamValue) {
+    match widget.source {
+        ParamSource::Settings => brush_settings.set_param(widget.name, value),
+        ParamSource::Brush => brush_settings.typ.set_param(widget.name, value)
+    }
+}
+
+// Same widgets, values may differ
+fn same_layout(a: &[(ParamSource, BrushParam)], b: &[(ParamSource, BrushParam)]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|((sa, pa), (sb, pb))| sa == sb && match (pa, pb) {
+        (BrushParam::Slider{name: na, min: mina, max: maxa, ..}, BrushParam::Slider{name: nb, min: minb, max: maxb, ..}) => {
+            na == nb && mina == minb && maxa == maxb
+        }
+        (BrushParam::Toggle{name: na, ..}, BrushParam::Toggle{name: nb, ..}) => na == nb,
+        (BrushParam::Dropdown{name: na, options: oa, ..}, BrushParam::Dropdown{name: nb, options: ob, ..}) => na == nb && oa == ob,
+        _ => false
+    })
+}
+
+fn label_text(param: &BrushParam) -> String {
+    match param {
+        BrushParam::Slider{name, value, ..} => format!("{name}: {value:.2}"),
+        BrushParam::Toggle{name, ..} | BrushParam::Dropdown{name, ..} => name.to_string()
+    }
+}
+
+fn slider_percent(value: f32, min: f32, max: f32) -> Val {
+    Val::Percent(((value - min) / (max - min).max(f32::EPSILON)).clamp(0.0, 1.0) * 100.0)
+}
+
+fn toggle_color(value: bool) -> Color {
+    match value {
+        true => Color::from(BLUE_500),
+        false => Color::from(GRAY_700)
+    }
+}
+
+fn text(value: impl Into<String>) -> impl Bundle {
+    (Text::new(value), TextFont{font_size: FONT_SIZE, ..default()}, TextColor(Color::from(GRAY_100)))
+}
+
+
+fn spawn_brush_panel(
+    mut commands: Commands
+){
+    commands.spawn((
+        Node {
+            position_type:  PositionType::Absolute,
+            top:            Val::Px(10.0),
+            right:          Val::Px(10.0),
+            width:          Val::Px(PANEL_WIDTH),
+            flex_direction: FlexDirection::Column,
+            row_gap:        Val::Px(4.0),
+            padding:        UiRect::all(Val::Px(8.0)),
+            display:        Display::None,
+            ..default()
+        },
+        BackgroundColor(Color::from(GRAY_900).with_alpha(0.85)),
+        BrushPanel::default()
+    ));
+}
+
+fn show_brush_panel(
+    controllers:    Query<(), With<BrushSelectController>>,
+    mut panels:     Query<&mut Node, With<BrushPanel>>
+){
+    let display = if controllers.is_empty() {Display::None} else {Display::Flex};
+    for mut node in panels.iter_mut(){
+        if node.display != display {
+            node.display = display;
+        }
+    }
+}
+
+fn spawn_param(parent: &mut ChildSpawnerCommands, source: ParamSource, param: &BrushParam) {
+    let widget = ParamWidget{source, name: param.name()};
+    parent.spawn((text(label_text(param)), ParamLabel, widget.clone()));
+    match param {
+        BrushParam::Slider{value, min, max, ..} => {
+            parent.spawn((
+                Node {
+                    width:  Val::Percent(100.0),
+                    height: Val::Px(10.0),
+                    ..default()
+                },
+                BackgroundColor(Color::from(GRAY_700)),
+                Interaction::default(),
+                RelativeCursorPosition::default(),
+                SliderTrack{min: *min, max: *max},
+                widget.clone()
+            )).with_children(|track| {
+                track.spawn((
+                    Node {
+                        width:  slider_percent(*value, *min, *max),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::from(BLUE_500)),
+                    SliderFill{min: *min, max: *max},
+                    widget.clone()
+                ));
+            });
+        }
+        BrushParam::Toggle{value, ..} => {
+            parent.spawn((
+                Button,
+                Node {
+                    width:  Val::Px(14.0),
+                    height: Val::Px(14.0),
+                    ..default()
+                },
+                BackgroundColor(toggle_color(*value)),
+                ToggleButton,
+                widget.clone()
+            ));
+        }
+        BrushParam::Dropdown{value, options, ..} => {
+            parent.spawn((
+                Button,
+                Node {
+                    padding: UiRect::axes(Val::Px(4.0), Val::Px(2.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::from(GRAY_700)),
+                DropdownButton,
+                widget.clone()
+            )).with_children(|button| {
+                button.spawn((text(options.get(*value).copied().unwrap_or_default()), DropdownText, widget.clone()));
+            });
+            parent.spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    display:        Display::None,
+                    ..default()
+                },
+                DropdownOptions,
+                widget.clone()
+            )).with_children(|list| {
+                for (i, option) in options.iter().enumerate() {
+                    list.spawn((
+                        Button,
+                        Node {
+                            padding: UiRect::axes(Val::Px(8.0), Val::Px(2.0)),
+                            ..default()
+                        },
+                        BackgroundColor(Color::from(GRAY_800)),
+                        DropdownOption(i),
+                        widget.clone()
+                    )).with_children(|button| {
+                        button.spawn(text(*option));
+                    });
+                }
+            });
+        }
+    }
+}
+
+// Rebuilds the panel when the parameters change shape, otherwise only updates the values
+#[allow(clippy::type_complexity)]
+fn update_brush_panel(
+    mut commands:       Commands,
+    brush_settings:     Res<BrushSettings>,
+    mut panels:         Query<(Entity, &mut BrushPanel)>,
+    mut labels:         Query<(&ParamWidget, &mut Text), With<ParamLabel>>,
+    mut dropdowns:      Query<(&ParamWidget, &mut Text), (With<DropdownText>, Without<ParamLabel>)>,
+    mut fills:          Query<(&ParamWidget, &SliderFill, &mut Node)>,
+    mut toggles:        Query<(&ParamWidget, &mut BackgroundColor), With<ToggleButton>>
+){
+    let params = panel_params(&brush_settings);
+    for (panel_entity, mut panel) in panels.iter_mut(){
+        if same_layout(&panel.params, &params) {
+            continue;
+        }
+        commands.entity(panel_entity).despawn_related::<Children>();
+        commands.entity(panel_entity).with_children(|parent| {
+            parent.spawn(text("Brush"));
+            for (source, param) in params.iter().filter(|(source, _)| *source == ParamSource::Settings) {
+                spawn_param(parent, *source, param);
+            }
+            if params.iter().any(|(source, _)| *source == ParamSource::Brush) {
+                parent.spawn(text(brush_settings.typ.name()));
+            }
+            for (source, param) in params.iter().filter(|(source, _)| *source == ParamSource::Brush) {
+                spawn_param(parent, *source, param);
+            }
+        });
+        panel.params = params.clone();
+    }
+
+    for (widget, mut text) in labels.iter_mut(){
+        let Some(param) = find_param(&brush_settings, widget) else {continue;};
+        let label = label_text(&param);
+        if text.0 != label {
+            text.0 = label;
+        }
+    }
+    for (widget, mut text) in dropdowns.iter_mut(){
+        let Some(BrushParam::Dropdown{value, options, ..}) = find_param(&brush_settings, widget) else {continue;};
+        let option = options.get(value).copied().unwrap_or_default();
+        if text.0 != option {
+            text.0 = option.to_string();
+        }
+    }
+    for (widget, fill, mut node) in fills.iter_mut(){
+        let Some(BrushParam::Slider{value, ..}) = find_param(&brush_settings, widget) else {continue;};
+        let width = slider_percent(value, fill.min, fill.max);
+        if node.width != width {
+            node.width = width;
+        }
+    }
+    for (widget, mut color) in toggles.iter_mut(){
+        let Some(BrushParam::Toggle{value, ..}) = find_param(&brush_settings, widget) else {continue;};
+        color.set_if_neq(BackgroundColor(toggle_color(value)));
+    }
+}
+
+fn drag_brush_slider(
+    mut brush_settings: ResMut<BrushSettings>,
+    sliders:            Query<(&Interaction, &RelativeCursorPosition, &SliderTrack, &ParamWidget)>
+){
+    for (interaction, cursor, track, widget) in sliders.iter(){
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        // Normalized position is -0.5..0.5 from the left to the right edge
+        let Some(normalized) = cursor.normalized else {continue;};
+        let t = (normalized.x + 0.5).clamp(0.0, 1.0);
+        let value = track.min + t * (track.max - track.min);
+        let current = find_param(&brush_settings, widget).and_then(|param| param.value().as_f32());
+        if current != Some(value) {
+            set_param(&mut brush_settings, widget, BrushParamValue::Float(value));
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_brush_toggle(
+    mut brush_settings: ResMut<BrushSettings>,
+    toggles:            Query<(&Interaction, &ParamWidget), (Changed<Interaction>, With<ToggleButton>)>
+){
+    for (interaction, widget) in toggles.iter(){
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(value) = find_param(&brush_settings, widget).and_then(|param| param.value().as_bool()) else {continue;};
+        set_param(&mut brush_settings, widget, BrushParamValue::Bool(!value));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_brush_dropdown(
+    buttons:    Query<(&Interaction, &ParamWidget), (Changed<Interaction>, With<DropdownButton>)>,
+    mut lists:  Query<(&ParamWidget, &mut Node), With<DropdownOptions>>
+){
+    for (interaction, widget) in buttons.iter(){
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        for (list_widget, mut node) in lists.iter_mut(){
+            if list_widget == widget {
+                node.display = if node.display == Display::None {Display::Flex} else {Display::None};
+            }
+        }
+    }
+}
+
+fn click_brush_option(
+    mut brush_settings: ResMut<BrushSettings>,
+    options:            Query<(&Interaction, &DropdownOption, &ParamWidget), Changed<Interaction>>,
+    mut lists:          Query<(&ParamWidget, &mut Node), With<DropdownOptions>>
+){
+    for (interaction, option, widget) in options.iter(){
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        set_param(&mut brush_settings, widget, BrushParamValue::Choice(option.0));
+        for (list_widget, mut node) in lists.iter_mut(){
+            if list_widget == widget {
+                node.display = Display::None;
+            }
+        }
+    }
+}
@@ -1,12 +1,30 @@
 use bevy::color::palettes::tailwind::BLUE_500;
 use bevy::light::{NotShadowCaster, NotShadowReceiver};
-use bevy_enhanced_input::prelude::Cancel;
+use bevy::picking::mesh_picking::ray_cast::{MeshRayCast, MeshRayCastSettings};
+use bevy_enhanced_input::prelude::{Cancel, Complete, Press};
 use bevy::prelude::*;
 use std::f32::consts::FRAC_PI_2;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use bevy_enhanced_input::prelude::*;
 use dyn_clone::DynClone;
 
 use crate::prelude::WorldPos;
+use crate::world_pos::UiHover;
+use crate::selection::{PASS_THROUGH, ToolKey};
+use crate::tracker::{Change, Changes};
+
+const DECAL_SEGMENTS: usize = 48;
+const DECAL_OFFSET: f32 = 0.05;
+const SURFACE_RAY_HEIGHT: f32 = 10000.0;
+const MIN_DAB_STEP: f32 = 0.01;
+const MIN_RADIUS: f32 = 0.5;
+const MAX_RADIUS: f32 = 100.0;
+const RADIUS_STEP: f32 = 1.1;       // Radius factor per key press or wheel notch
+const STRENGTH_STEP: f32 = 0.05;
+const DRAG_STEPS_PER_PIXEL: f32 = 0.05;
+
+static NEXT_STROKE_ID: AtomicU64 = AtomicU64::new(0);
 
 
 pub struct PGEditorBrushSelectPlugin;
@@ -20,57 +38,283 @@ impl Plugin for PGEditorBrushSelectPlugin {
         .add_observer(start_brush)
         .add_observer(update_brush)
         .add_observer(end_brush)
+        .add_observer(release_brush)
+        .add_observer(complete_brush)
+        .add_observer(step_brush_size)
+        .add_observer(scroll_brush_size)
+        .add_observer(drag_brush_size)
+        .add_systems(Startup, setup_brush_assets)
+        .add_systems(Update, resize_brush_mesh.run_if(resource_changed::<BrushSettings>))
+        .add_systems(Update, 
+            (
+                hover_brush,
+                draw_brush_decal.run_if(any_with_component::<BrushMarker>)
+            ).chain()
+        )
         .add_systems(Update, 
             (
-                brush_started.run_if(on_message::<BrushStart>),
+                brush_started.run_if(on_message::<BrushStart>.and(resource_exists::<Brush>)),
                 brush_apply.run_if(resource_exists::<Brush>),
-                brush_final.run_if(on_message::<BrushDone>)
-            )
+                brush_final.run_if(on_message::<BrushDone>.and(resource_exists::<Brush>))
+            ).chain()
         )
-        .add_systems(Update, brush_apply.run_if(brush_changed))
         ;
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum BrushCursor {
+    Disc,       // Flat circle mesh above the cursor
+    #[default]
+    Decal       // Rings following the BrushSurface entities
+}
+
+// Weight from the inner (hardness) ring, t = 0, to the outer ring, t = 1
+#[derive(Clone, Default)]
+pub enum BrushFalloff {
+    Linear,
+    #[default]
+    Smooth,
+    Sphere,
+    Constant,
+    Custom(Arc<dyn Curve<f32> + Send + Sync>)   // Sampled on 0..1
+}
+
+impl BrushFalloff {
+    pub fn sample(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            BrushFalloff::Linear => 1.0 - t,
+            BrushFalloff::Smooth => 1.0 - t * t * (3.0 - 2.0 * t),
+            BrushFalloff::Sphere => (1.0 - t * t).sqrt(),
+            BrushFalloff::Constant => 1.0,
+            BrushFalloff::Custom(curve) => curve.sample_clamped(t).clamp(0.0, 1.0)
+        }
+    }
+
+    pub fn weight(&self, distance: f32, radius: f32, hardness: f32, strength: f32) -> f32 {
+        if distance >= radius {
+            return 0.0;
+        }
+        let inner = radius * hardness.clamp(0.0, 1.0);
+        if distance <= inner {
+            return strength;
+        }
+        strength * self.sample((distance - inner) / (radius - inner))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum BrushApplyMode {
+    Continuous,     // Every frame, dabs share the frame's delta time
+    #[default]
+    OnMove,         // Only when the brush moved by the spacing
+    FixedRate(f32)  // Dabs per second, independent of the frame rate
+}
+
 #[derive(Resource)]
 pub struct BrushSettings {
     pub radius: f32,
+    pub strength: f32,
+    pub hardness: f32,  // Fraction of the radius at full strength, drawn as the inner ring
+    pub falloff: BrushFalloff,
+    pub spacing: f32,   // Distance between dabs as a fraction of the radius
+    pub apply_mode: BrushApplyMode,
+    pub cursor: BrushCursor,
+    pub hover: bool,    // Show the marker whenever a BrushSelectController exists
     pub typ: Box<dyn BrushType>
 }
 impl Default for BrushSettings {
     fn default() -> Self {
         Self {
             radius: 10.0,
+            strength: 1.0,
+            hardness: 0.5,
+            falloff: BrushFalloff::Smooth,
+            spacing: 0.25,
+            apply_mode: BrushApplyMode::OnMove,
+            cursor: BrushCursor::Decal,
+            hover: true,
             typ: Box::new(NothingBrush)            
         }
     }
 }
 
+const FALLOFFS: [&str; 4] = ["Linear", "Smooth", "Sphere", "Constant"];
+const APPLY_MODES: [&str; 3] = ["Continuous", "On move", "Fixed rate"];
+const CURSORS: [&str; 2] = ["Disc", "Decal"];
+
+impl BrushSettings {
+    // Settings shared by every brush type, the brush type adds its own through BrushType::params
+    pub fn params(&self) -> Vec<BrushParam> {
+        let falloff = match self.falloff {
+            BrushFalloff::Linear => 0,
+            BrushFalloff::Smooth => 1,
+            BrushFalloff::Sphere => 2,
+            BrushFalloff::Constant => 3,
+            BrushFalloff::Custom(_) => 4
+        };
+        let mut falloffs = FALLOFFS.to_vec();
+        if falloff == 4 {
+            falloffs.push("Custom");
+        }
+        let apply_mode = match self.apply_mode {
+            BrushApplyMode::Continuous => 0,
+            BrushApplyMode::OnMove => 1,
+            BrushApplyMode::FixedRate(_) => 2
+        };
+        let mut params = vec![
+            BrushParam::Slider{name: "radius", value: self.radius, min: MIN_RADIUS, max: MAX_RADIUS},
+            BrushParam::Slider{name: "strength", value: self.strength, min: 0.0, max: 1.0},
+            BrushParam::Slider{name: "hardness", value: self.hardness, min: 0.0, max: 1.0},
+            BrushParam::Dropdown{name: "falloff", value: falloff, options: falloffs},
+            BrushParam::Slider{name: "spacing", value: self.spacing, min: 0.05, max: 2.0},
+            BrushParam::Dropdown{name: "apply mode", value: apply_mode, options: APPLY_MODES.to_vec()}
+        ];
+        if let BrushApplyMode::FixedRate(rate) = self.apply_mode {
+            params.push(BrushParam::Slider{name: "rate", value: rate, min: 1.0, max: 60.0});
+        }
+        params.push(BrushParam::Dropdown{name: "cursor", value: self.cursor as usize, options: CURSORS.to_vec()});
+        params.push(BrushParam::Toggle{name: "hover", value: self.hover});
+        params
+    }
+
+    pub fn set_param(&mut self, name: &str, value: BrushParamValue) {
+        match (name, value) {
+            ("radius", BrushParamValue::Float(v)) => self.radius = v.clamp(MIN_RADIUS, MAX_RADIUS),
+            ("strength", BrushParamValue::Float(v)) => self.strength = v,
+            ("hardness", BrushParamValue::Float(v)) => self.hardness = v.clamp(0.0, 1.0),
+            ("falloff", BrushParamValue::Choice(i)) => {
+                self.falloff = match i {
+                    0 => BrushFalloff::Linear,
+                    1 => BrushFalloff::Smooth,
+                    2 => BrushFalloff::Sphere,
+                    3 => BrushFalloff::Constant,
+                    _ => return
+                };
+            }
+            ("spacing", BrushParamValue::Float(v)) => self.spacing = v,
+            ("apply mode", BrushParamValue::Choice(i)) => {
+                self.apply_mode = match i {
+                    0 => BrushApplyMode::Continuous,
+                    1 => BrushApplyMode::OnMove,
+                    _ => BrushApplyMode::FixedRate(10.0)
+                };
+            }
+            ("rate", BrushParamValue::Float(v)) => self.apply_mode = BrushApplyMode::FixedRate(v.max(0.1)),
+            ("cursor", BrushParamValue::Choice(i)) => {
+                self.cursor = if i == 0 {BrushCursor::Disc} else {BrushCursor::Decal};
+            }
+            ("hover", BrushParamValue::Bool(v)) => self.hover = v,
+            _ => {}
+        }
+    }
+}
+
+// Ground the brush is painted on, used for the decal and surface sampling
+#[derive(Component, Default, Reflect)]
+pub struct BrushSurface;
+
+// Point on the BrushSurface straight below `loc`
+pub fn sample_surface(
+    ray_cast: &mut MeshRayCast,
+    surfaces: &Query<(), With<BrushSurface>>,
+    loc:      Vec2
+) -> Option<(Vec3, Vec3)> {
+    let ray = Ray3d::new(Vec3::new(loc.x, SURFACE_RAY_HEIGHT, loc.y), Dir3::NEG_Y);
+    let filter = |entity: Entity| surfaces.contains(entity);
+    let settings = MeshRayCastSettings::default().with_filter(&filter);
+    ray_cast
+        .cast_ray(ray, &settings)
+        .first()
+        .map(|(_, hit)| (hit.point, hit.normal.normalize_or(Vec3::Y)))
+}
+
+
+// Marker mesh is rebuilt only when BrushSettings::radius changes
+#[derive(Resource)]
+pub struct BrushAssets {
+    pub mesh:     Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+    radius:       f32
+}
+
+fn setup_brush_assets(
+    mut commands:   Commands,
+    mut meshes:     ResMut<Assets<Mesh>>,
+    mut materials:  ResMut<Assets<StandardMaterial>>,
+    brush_settings: Res<BrushSettings>
+){
+    commands.insert_resource(BrushAssets{
+        mesh:     meshes.add(Circle::new(brush_settings.radius)),
+        material: materials.add(Color::from(BLUE_500).with_alpha(0.4)),
+        radius:   brush_settings.radius
+    });
+}
+
+fn resize_brush_mesh(
+    mut meshes:         ResMut<Assets<Mesh>>,
+    brush_settings:     Res<BrushSettings>,
+    assets:             Option<ResMut<BrushAssets>>
+){
+    let Some(mut assets) = assets else {return;};
+    if assets.radius == brush_settings.radius {
+        return;
+    }
+    if let Some(mesh) = meshes.get_mut(&assets.mesh) {
+        *mesh = Circle::new(brush_settings.radius).into();
+    }
+    assets.radius = brush_settings.radius;
+}
+
 
 fn brush_started(
     world:   &mut World,
 ){
     world.resource_scope(|_world: &mut World, mut brush: Mut<Brush>| {
-        brush.typ.started(_world);
+        let Brush{typ, stroke, ..} = &mut *brush;
+        if let Some(keys) = _world.get_resource::<ButtonInput<KeyCode>>() {
+            stroke.read_modifiers(keys);
+        }
+        typ.started(_world, stroke);
     });
 }
 
 fn brush_apply(
     world:     &mut World,
 ){
+    let delta = world.get_resource::<Time>().map(|time| time.delta_secs()).unwrap_or(0.0);
     world.resource_scope(|_world: &mut World, mut brush: Mut<Brush>| {
-        let radius = brush.radius;
-        let loc = brush.loc;
-        brush.typ.apply(_world, loc, radius);
+        if brush.ended {
+            return;
+        }
+        let dabs = brush.advance(delta);
+        let Brush{typ, stroke, ..} = &mut *brush;
+        if let Some(keys) = _world.get_resource::<ButtonInput<KeyCode>>() {
+            stroke.read_modifiers(keys);
+        }
+        for dab in dabs {
+            stroke.push(dab.loc);
+            typ.apply(_world, &dab, stroke);
+        }
     });
 }
 
+// Also queued by start_brush, so only an ended stroke is finished
 fn brush_final(
     world:     &mut World,
 ){
-    world.resource_scope(|_world: &mut World, mut brush: Mut<Brush>| {
-        brush.typ.done(_world);
+    if !world.get_resource::<Brush>().is_some_and(|brush| brush.ended) {
+        return;
+    }
+    let change = world.resource_scope(|_world: &mut World, mut brush: Mut<Brush>| {
+        let Brush{typ, stroke, ..} = &mut *brush;
+        typ.done(_world, stroke)
     });
+    // The whole stroke is a single undo step
+    if let Some(change) = change && let Some(mut changes) = world.get_resource_mut::<Changes>() {
+        changes.record(change);
+    }
 
     world.remove_resource::<Brush>();
 }
@@ -85,17 +329,38 @@ pub fn brush_select_controller() -> impl Bundle {
         Actions::<BrushSelectController>::spawn(
             SpawnWith(|context: &mut ActionSpawner<_>| {
             let member1 = context
-                .spawn((Action::<BrushSelectUpdate1>::new(), Down::default(), bindings![KeyCode::KeyJ]))
+                .spawn((Action::<BrushSelectUpdate1>::new(), Down::default(), PASS_THROUGH, ToolKey, bindings![KeyCode::KeyJ]))
                 .id();
             let member2 = context
-                .spawn((Action::<BrushSelectUpdate2>::new(), Down::default(), bindings![MouseButton::Left]))
+                .spawn((Action::<BrushSelectUpdate2>::new(), Down::default(), PASS_THROUGH, bindings![MouseButton::Left]))
                 .id();
             context.spawn((Action::<BrushSelectUpdate>::new(), Chord::new([member1, member2])));
 
+            // Radius, or strength with Shift held
+            context.spawn((
+                Action::<BrushAdjustStep>::new(), 
+                Press::default(), 
+                Bindings::spawn(Bidirectional{positive: Binding::from(KeyCode::BracketRight), negative: Binding::from(KeyCode::BracketLeft)})
+            ));
+            context.spawn((Action::<BrushAdjustScroll>::new(), bindings![Binding::mouse_wheel().with_mod_keys(ModKeys::CONTROL)]));
+            context.spawn((Action::<BrushAdjustDrag>::new(), bindings![Binding::mouse_motion().with_mod_keys(ModKeys::ALT)]));
+
         })) 
     );
 }
 
+#[derive(InputAction)]
+#[action_output(f32)]
+struct BrushAdjustStep;
+
+#[derive(InputAction)]
+#[action_output(Vec2)]
+struct BrushAdjustScroll;
+
+#[derive(InputAction)]
+#[action_output(Vec2)]
+struct BrushAdjustDrag;
+
 
 #[derive(InputAction)]
 #[action_output(bool)]
@@ -110,74 +375,278 @@ struct BrushSelectUpdate2;
 struct BrushSelectUpdate;
 
 
+// Set for a press that began off the surface or over the UI, until the chord stops firing
+#[derive(Resource)]
+struct BrushPressIgnored;
+
+// A stroke lasts while the chord fires. The chord also fires again without a Start
+// when the mouse is pressed while J is still held, so every Fire without a Brush begins one
+#[allow(clippy::too_many_arguments)]
 fn start_brush(
-    _trigger:          On<Start<BrushSelectUpdate>>,
+    _trigger:          On<Fire<BrushSelectUpdate>>,
     input_data:        Res<WorldPos>,
     mut commands:      Commands,
-    mut meshes:        ResMut<Assets<Mesh>>,
-    mut materials:     ResMut<Assets<StandardMaterial>>,
+    assets:            Res<BrushAssets>,
     brush_settings:    Res<BrushSettings>,
+    ui_hover:          UiHover,
+    brush:             Option<Res<Brush>>,
+    ignored:           Option<Res<BrushPressIgnored>>,
     brushes:           Query<Entity, With<BrushMarker>>
 ){
+    if brush.as_ref().is_some_and(|brush| !brush.ended) || ignored.is_some() {
+        return;
+    }
+    let Some(world_pos) = input_data.get().filter(|_| !ui_hover.any()) else {
+        commands.insert_resource(BrushPressIgnored);
+        return;
+    };
     for brush_entity in brushes.iter(){
         commands.entity(brush_entity).despawn();
     }
+    // The previous stroke still has to reach Changes before it is replaced
+    if brush.is_some() {
+        commands.queue(brush_final);
+    }
 
-    let Some(world_pos) = input_data.get() else {return;};
-    let loc = Vec3::new(world_pos.x, world_pos.y + 1.0, world_pos.z);
-    let brush = Brush{
-        loc, 
-        radius: brush_settings.radius, 
-        typ: brush_settings.typ.clone()
-    };
+    commands.insert_resource(Brush::new(world_pos, &brush_settings));
+    spawn_brush_marker(&mut commands, &assets, &brush_settings, world_pos + Vec3::Y);
+    commands.write_message(BrushStart);
+}
 
-    commands.insert_resource(brush);
-    commands.spawn((
-        Mesh3d(meshes.add(Circle::new(brush_settings.radius))),
-        MeshMaterial3d(materials.add(Color::from(BLUE_500).with_alpha(0.4))),
-        Transform::from_xyz(world_pos.x, world_pos.y + 1.0, world_pos.z)
+fn spawn_brush_marker(
+    commands:       &mut Commands,
+    assets:         &BrushAssets,
+    brush_settings: &BrushSettings,
+    loc:            Vec3
+) -> Entity {
+    let mut marker = commands.spawn((
+        Transform::from_translation(loc)
                   .with_rotation(Quat::from_rotation_x(-FRAC_PI_2)),
+        Visibility::default(),
         BrushMarker,
         NotShadowCaster,
         NotShadowReceiver
     ));
-    commands.write_message(BrushStart);
+    if brush_settings.cursor == BrushCursor::Disc {
+        marker.insert((
+            Mesh3d(assets.mesh.clone()),
+            MeshMaterial3d(assets.material.clone())
+        ));
+    }
+    marker.id()
+}
+
+// Keeps the marker under the cursor while the brush tool is active but not painting
+fn hover_brush(
+    mut commands:       Commands,
+    input_data:         Res<WorldPos>,
+    assets:             Option<Res<BrushAssets>>,
+    brush_settings:     Res<BrushSettings>,
+    brush:              Option<Res<Brush>>,
+    controllers:        Query<(), With<BrushSelectController>>,
+    mut markers:        Query<(Entity, &mut Transform, &mut Visibility), With<BrushMarker>>
+){
+    if brush.is_some() {
+        return;
+    }
+    if !brush_settings.hover || controllers.is_empty() {
+        for (marker_entity, _, _) in markers.iter(){
+            commands.entity(marker_entity).despawn();
+        }
+        return;
+    }
+    let Some(world_pos) = input_data.get() else {
+        for (_, _, mut visibility) in markers.iter_mut(){
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+        return;
+    };
+    let loc = Vec3::new(world_pos.x, world_pos.y + 1.0, world_pos.z);
+    if markers.is_empty() {
+        let Some(assets) = assets else {return;};
+        spawn_brush_marker(&mut commands, &assets, &brush_settings, loc);
+        return;
+    }
+    for (_, mut transform, mut visibility) in markers.iter_mut(){
+        visibility.set_if_neq(Visibility::Inherited);
+        if transform.translation != loc {
+            transform.translation = loc;
+        }
+    }
+}
 
-    // Stuck on Assets as Entities
-    // for (entity, terrain_material) in textures.iter_mut(){
-    //     if let Some(material) = materials.get(&terrain_material.0){
-    //         if let Some(ref base_color_texture) = material.base_color_texture {
-    //             if let Some(image) = images.get_mut(base_color_texture){
-    //                 let v = image.data.as_ref().unwrap()[0];
-    //                 let count = image.data.as_ref().unwrap().len();
-    //                 image.data = Some(vec![255; count]);
-    //             }
-    //             break;
-    //         }
-    //     }
-    // }
+// Ring points on the surface, only cast again when the marker, radius or surface meshes change
+#[derive(Default)]
+struct DecalRings {
+    key:   Option<(Vec3, f32, f32)>,
+    rings: Vec<(Vec<Vec3>, Color)>
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_brush_decal(
+    mut gizmos:         Gizmos,
+    mut ray_cast:       MeshRayCast,
+    mut cache:          Local<DecalRings>,
+    mut mesh_events:    MessageReader<AssetEvent<Mesh>>,
+    brush_settings:     Res<BrushSettings>,
+    input_data:         Res<WorldPos>,
+    surfaces:           Query<(), With<BrushSurface>>,
+    markers:            Query<(&Transform, &Visibility), With<BrushMarker>>
+){
+    let meshes_changed = mesh_events.read().any(|event| matches!(event, AssetEvent::Modified{..}));
+    if brush_settings.cursor != BrushCursor::Decal {
+        return;
+    }
+    let color = Color::from(BLUE_500);
+    for (transform, visibility) in markers.iter(){
+        if visibility == Visibility::Hidden {
+            continue;
+        }
+        let key = (transform.translation, brush_settings.radius, brush_settings.hardness);
+        if meshes_changed || cache.key != Some(key) {
+            let center = transform.translation.xz();
+            let fallback_y = input_data.get().map(|p| p.y).unwrap_or(transform.translation.y - 1.0);
+            let mut rings = vec![(brush_settings.radius, color)];
+            if brush_settings.hardness > 0.0 && brush_settings.hardness < 1.0 {
+                rings.push((brush_settings.radius * brush_settings.hardness, color.with_alpha(0.5)));
+            }
+            cache.rings = rings.into_iter().map(|(radius, color)| {
+                let points: Vec<Vec3> = (0..=DECAL_SEGMENTS).map(|i| {
+                    let angle = i as f32 / DECAL_SEGMENTS as f32 * std::f32::consts::TAU;
+                    let loc = center + Vec2::from_angle(angle) * radius;
+                    match sample_surface(&mut ray_cast, &surfaces, loc) {
+                        Some((point, normal)) => point + normal * DECAL_OFFSET,
+                        None => Vec3::new(loc.x, fallback_y + DECAL_OFFSET, loc.y)
+                    }
+                }).collect();
+                (points, color)
+            }).collect();
+            cache.key = Some(key);
+        }
+        for (points, color) in cache.rings.iter() {
+            gizmos.linestrip(points.iter().copied(), *color);
+        }
+    }
 }
 
 fn update_brush(
-    _trigger:               On<Fire<BrushSelectUpdate>>,
-    input_data:             Res<WorldPos>,
-    mut brush_transform:    Single<&mut Transform, With<BrushMarker>>,
-    mut brush:              ResMut<Brush>
+    _trigger:       On<Fire<BrushSelectUpdate>>,
+    input_data:     Res<WorldPos>,
+    brush:          Option<ResMut<Brush>>,
+    mut markers:    Query<&mut Transform, With<BrushMarker>>
 ){
+    let Some(mut brush) = brush.filter(|brush| !brush.ended) else {return;};
     let Some(world_pos) = input_data.get() else {return;};
     if world_pos.xz() != brush.loc.xz(){
-        brush.loc = Vec3::new(world_pos.x, world_pos.y + 1.0, world_pos.z);
-        brush_transform.translation = brush.loc;
+        brush.loc = world_pos;
+        for mut transform in markers.iter_mut(){
+            transform.translation = world_pos + Vec3::Y;
+        }
+    }
+}
+
+fn finish_brush(
+    commands:   &mut Commands,
+    brush:      Option<ResMut<Brush>>,
+    markers:    &Query<Entity, With<BrushMarker>>
+){
+    commands.remove_resource::<BrushPressIgnored>();
+    let Some(mut brush) = brush.filter(|brush| !brush.ended) else {return;};
+    brush.ended = true;
+    commands.write_message(BrushDone);
+    for marker_entity in markers.iter(){
+        commands.entity(marker_entity).despawn();
     }
 }
 
 fn end_brush(
     _trigger:       On<Cancel<BrushSelectUpdate>>,
     mut commands:   Commands,
-    brush_entity:   Single<Entity, With<BrushMarker>>,
+    brush:          Option<ResMut<Brush>>,
+    markers:        Query<Entity, With<BrushMarker>>
 ){
-    commands.write_message(BrushDone);
-    commands.entity(*brush_entity).despawn();
+    finish_brush(&mut commands, brush, &markers);
+}
+
+// Both keys released in the same frame, or inputs reset on focus loss
+fn complete_brush(
+    _trigger:       On<Complete<BrushSelectUpdate>>,
+    mut commands:   Commands,
+    brush:          Option<ResMut<Brush>>,
+    markers:        Query<Entity, With<BrushMarker>>
+){
+    finish_brush(&mut commands, brush, &markers);
+}
+
+// Mouse released with J still held, the chord drops back to Ongoing without a Cancel
+fn release_brush(
+    _trigger:       On<Ongoing<BrushSelectUpdate>>,
+    mut commands:   Commands,
+    brush:          Option<ResMut<Brush>>,
+    markers:        Query<Entity, With<BrushMarker>>
+){
+    finish_brush(&mut commands, brush, &markers);
+}
+
+// f32::signum is 1.0 for zero
+fn step_sign(value: f32) -> f32 {
+    if value == 0.0 {0.0} else {value.signum()}
+}
+
+// Grows the radius by RADIUS_STEP per step, or the strength by STRENGTH_STEP with Shift held.
+// Only writes when a value changes, so BrushSettings isn't marked changed at the limits
+fn adjust_brush(
+    steps:              f32,
+    keys:               &ButtonInput<KeyCode>,
+    mut brush_settings: Mut<BrushSettings>,
+    brush:              Option<Mut<Brush>>
+){
+    if steps == 0.0 {
+        return;
+    }
+    let (mut radius, mut strength) = (brush_settings.radius, brush_settings.strength);
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        strength = (strength + steps * STRENGTH_STEP).clamp(0.0, 1.0);
+    } else {
+        radius = (radius * RADIUS_STEP.powf(steps)).clamp(MIN_RADIUS, MAX_RADIUS);
+    }
+    if radius == brush_settings.radius && strength == brush_settings.strength {
+        return;
+    }
+    brush_settings.radius = radius;
+    brush_settings.strength = strength;
+    if let Some(mut brush) = brush {
+        brush.radius = radius;
+        brush.strength = strength;
+    }
+}
+
+fn step_brush_size(
+    trigger:            On<Start<BrushAdjustStep>>,
+    keys:               Res<ButtonInput<KeyCode>>,
+    brush_settings:     ResMut<BrushSettings>,
+    brush:              Option<ResMut<Brush>>
+){
+    adjust_brush(step_sign(trigger.event().value), &keys, brush_settings.into(), brush.map(|brush| brush.into()));
+}
+
+fn scroll_brush_size(
+    trigger:            On<Fire<BrushAdjustScroll>>,
+    keys:               Res<ButtonInput<KeyCode>>,
+    brush_settings:     ResMut<BrushSettings>,
+    brush:              Option<ResMut<Brush>>
+){
+    // Line and pixel scrolling differ wildly in size, one step per event either way
+    adjust_brush(step_sign(trigger.event().value.y), &keys, brush_settings.into(), brush.map(|brush| brush.into()));
+}
+
+fn drag_brush_size(
+    trigger:            On<Fire<BrushAdjustDrag>>,
+    keys:               Res<ButtonInput<KeyCode>>,
+    brush_settings:     ResMut<BrushSettings>,
+    brush:              Option<ResMut<Brush>>
+){
+    adjust_brush(trigger.event().value.x * DRAG_STEPS_PER_PIXEL, &keys, brush_settings.into(), brush.map(|brush| brush.into()));
 }
 
 #[derive(Message)]
@@ -194,9 +663,177 @@ pub struct BrushMarker;
 pub struct Brush {
     loc: Vec3,
     radius: f32,
+    strength: f32,
+    hardness: f32,
+    falloff: BrushFalloff,
+    spacing: f32,
+    apply_mode: BrushApplyMode,
+    last_dab: Option<Vec3>,
+    accumulated: f32,
+    ended: bool,        // BrushDone was written, brush_final hasn't run yet
+    stroke: BrushStroke,
     typ: Box<dyn BrushType>
 }
 
+impl Brush {
+    pub fn new(loc: Vec3, brush_settings: &BrushSettings) -> Self {
+        Brush{
+            loc, 
+            radius: brush_settings.radius, 
+            strength: brush_settings.strength,
+            hardness: brush_settings.hardness,
+            falloff: brush_settings.falloff.clone(),
+            spacing: brush_settings.spacing,
+            apply_mode: brush_settings.apply_mode,
+            last_dab: None,
+            accumulated: 0.0,
+            ended: false,
+            stroke: BrushStroke::new(loc),
+            typ: brush_settings.typ.clone()
+        }
+    }
+
+    pub fn stroke(&self) -> &BrushStroke {
+        &self.stroke
+    }
+
+    pub fn loc(&self) -> Vec3 {
+        self.loc
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn weight(&self, point: Vec3) -> f32 {
+        self.falloff.weight(self.loc.xz().distance(point.xz()), self.radius, self.hardness, self.strength)
+    }
+
+    // Evenly spaced points from the last dab towards loc, the first call dabs at loc
+    fn stroke_dabs(&mut self) -> Vec<Vec3> {
+        let Some(mut last) = self.last_dab else {
+            self.last_dab = Some(self.loc);
+            return vec![self.loc];
+        };
+        let step = (self.spacing * self.radius).max(MIN_DAB_STEP);
+        let mut dabs = Vec::new();
+        let mut distance = last.xz().distance(self.loc.xz());
+        while distance >= step {
+            last = last.lerp(self.loc, step / distance);
+            dabs.push(last);
+            distance = last.xz().distance(self.loc.xz());
+        }
+        self.last_dab = Some(last);
+        dabs
+    }
+
+    // Dabs for a frame that took `delta` seconds, according to the apply mode
+    fn advance(&mut self, delta: f32) -> Vec<BrushDab> {
+        let dabs: Vec<(Vec3, f32)> = match self.apply_mode {
+            BrushApplyMode::OnMove => {
+                let locs = self.stroke_dabs();
+                let elapsed = delta / locs.len().max(1) as f32;
+                locs.into_iter().map(|loc| (loc, elapsed)).collect()
+            }
+            BrushApplyMode::Continuous => {
+                let mut locs = self.stroke_dabs();
+                if locs.is_empty() {
+                    locs.push(self.loc);
+                }
+                let elapsed = delta / locs.len() as f32;
+                locs.into_iter().map(|loc| (loc, elapsed)).collect()
+            }
+            BrushApplyMode::FixedRate(rate) => {
+                let interval = 1.0 / rate.max(f32::EPSILON);
+                let Some(last) = self.last_dab else {
+                    self.last_dab = Some(self.loc);
+                    return vec![self.dab_at(self.loc, interval)];
+                };
+                self.accumulated += delta;
+                let count = (self.accumulated / interval).floor() as usize;
+                self.accumulated -= count as f32 * interval;
+                self.last_dab = Some(self.loc);
+                (1..=count)
+                    .map(|i| (last.lerp(self.loc, i as f32 / count as f32), interval))
+                    .collect()
+            }
+        };
+        dabs.into_iter().map(|(loc, elapsed)| self.dab_at(loc, elapsed)).collect()
+    }
+
+    fn dab_at(&self, loc: Vec3, elapsed: f32) -> BrushDab {
+        BrushDab {
+            elapsed,
+            loc,
+            radius: self.radius,
+            strength: self.strength,
+            hardness: self.hardness,
+            falloff: self.falloff.clone()
+        }
+    }
+}
+
+// Everything painted since the button went down, shared by all calls to the BrushType
+#[derive(Clone, Debug, Default)]
+pub struct BrushStroke {
+    pub id:     u64,
+    pub start:  Vec3,
+    pub dabs:   Vec<Vec3>,      // Dabs applied so far, the current one included
+    pub length: f32,            // XZ distance along the dabs
+    pub shift:  bool,           // Modifiers currently held, Shift inverts and Ctrl smooths
+    pub ctrl:   bool,
+    pub alt:    bool
+}
+
+impl BrushStroke {
+    pub fn new(start: Vec3) -> Self {
+        BrushStroke {
+            id: NEXT_STROKE_ID.fetch_add(1, Ordering::Relaxed),
+            start,
+            ..default()
+        }
+    }
+
+    pub fn invert(&self) -> bool {
+        self.shift
+    }
+
+    pub fn smooth(&self) -> bool {
+        self.ctrl
+    }
+
+    fn push(&mut self, loc: Vec3) {
+        if let Some(last) = self.dabs.last() {
+            self.length += last.xz().distance(loc.xz());
+        }
+        self.dabs.push(loc);
+    }
+
+    fn read_modifiers(&mut self, keys: &ButtonInput<KeyCode>) {
+        self.shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        self.ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        self.alt = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    }
+}
+
+// Single application of the brush passed to BrushType::apply
+#[derive(Clone)]
+pub struct BrushDab {
+    pub elapsed: f32,   // Seconds of brushing this dab stands for
+    pub loc: Vec3,
+    pub radius: f32,
+    pub strength: f32,
+    pub hardness: f32,
+    pub falloff: BrushFalloff
+}
+
+impl BrushDab {
+    // Weight at a world point, measured on XZ
+    pub fn weight(&self, point: Vec3) -> f32 {
+        self.falloff.weight(self.loc.xz().distance(point.xz()), self.radius, self.hardness, self.strength)
+    }
+}
+
 
 pub fn brush_changed(
     maybe_brush: Option<Res<Brush>>
@@ -209,10 +846,68 @@ pub fn brush_changed(
 }
 
 
+// Parameter shown in the brush panel, the name is passed back to set_param
+#[derive(Clone, Debug, PartialEq)]
+pub enum BrushParam {
+    Slider{name: &'static str, value: f32, min: f32, max: f32},
+    Toggle{name: &'static str, value: bool},
+    Dropdown{name: &'static str, value: usize, options: Vec<&'static str>}
+}
+
+impl BrushParam {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BrushParam::Slider{name, ..} | BrushParam::Toggle{name, ..} | BrushParam::Dropdown{name, ..} => name
+        }
+    }
+
+    pub fn value(&self) -> BrushParamValue {
+        match self {
+            BrushParam::Slider{value, ..} => BrushParamValue::Float(*value),
+            BrushParam::Toggle{value, ..} => BrushParamValue::Bool(*value),
+            BrushParam::Dropdown{value, ..} => BrushParamValue::Choice(*value)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushParamValue {
+    Float(f32),
+    Bool(bool),
+    Choice(usize)
+}
+
+impl BrushParamValue {
+    pub fn as_f32(self) -> Option<f32> {
+        match self {
+            BrushParamValue::Float(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(self) -> Option<bool> {
+        match self {
+            BrushParamValue::Bool(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_choice(self) -> Option<usize> {
+        match self {
+            BrushParamValue::Choice(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+
 pub trait BrushType:  Send + Sync + DynClone + 'static {
-    fn started(&mut self, world:&mut World){}
-    fn apply(&mut self, world: &mut World, loc: Vec3, radius: f32){}
-    fn done(&mut self, world: &mut World){}
+    fn started(&mut self, _world: &mut World, _stroke: &BrushStroke){}
+    fn apply(&mut self, _world: &mut World, _dab: &BrushDab, _stroke: &BrushStroke){}
+    fn done(&mut self, _world: &mut World, _stroke: &BrushStroke) -> Option<Box<dyn Change>> {None}
+    fn name(&self) -> &str {"Brush"}
+    fn params(&self) -> Vec<BrushParam> {Vec::new()}
+    fn set_param(&mut self, _name: &str, _value: BrushParamValue){}
 }
 dyn_clone::clone_trait_object!(BrushType);
 
@@ -220,14 +915,228 @@ dyn_clone::clone_trait_object!(BrushType);
 pub struct NothingBrush;
 
 impl BrushType for NothingBrush {
-    fn started(&mut self, world:&mut World) {
+    fn started(&mut self, _world: &mut World, _stroke: &BrushStroke) {
         // info!("Started nothingbrush");
     }
-    fn apply(&mut self, world: &mut World, loc: Vec3, radius: f32) {
+    fn apply(&mut self, _world: &mut World, _dab: &BrushDab, _stroke: &BrushStroke) {
         // info!("apply nothingbrush");
     }
 
-    fn done(&mut self, world: &mut World) {
+    fn done(&mut self, _world: &mut World, _stroke: &BrushStroke) -> Option<Box<dyn Change>> {
         // info!("Done nothingbrush");
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[derive(Clone)]
+    struct CountBrush(Arc<AtomicUsize>);
+
+    impl BrushType for CountBrush {
+        fn apply(&mut self, _world: &mut World, _dab: &BrushDab, _stroke: &BrushStroke) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn test_brush(apply_mode: BrushApplyMode) -> (Brush, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let brush_settings = BrushSettings {
+            radius: 4.0,
+            spacing: 0.25,
+            apply_mode,
+            typ: Box::new(CountBrush(count.clone())),
+            ..default()
+        };
+        (Brush::new(Vec3::ZERO, &brush_settings), count)
+    }
+
+    // Runs `frames` frames of `delta` seconds, moving the brush by `step` on X each frame
+    fn run_frames(apply_mode: BrushApplyMode, frames: usize, delta: f32, step: f32) -> usize {
+        let (brush, count) = test_brush(apply_mode);
+        let mut app = App::new();
+        app.insert_resource(Time::<()>::default());
+        app.insert_resource(brush);
+        app.add_systems(Update, brush_apply.run_if(resource_exists::<Brush>));
+        for _ in 0..frames {
+            app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs_f32(delta));
+            app.update();
+            app.world_mut().resource_mut::<Brush>().loc.x += step;
+        }
+        count.load(Ordering::Relaxed)
     }
-}
\ No newline at end of file
+
+    #[derive(Clone)]
+    struct TraceBrush(Arc<Mutex<Vec<Vec3>>>);
+
+    impl BrushType for TraceBrush {
+        fn apply(&mut self, _world: &mut World, dab: &BrushDab, _stroke: &BrushStroke) {
+            self.0.lock().unwrap().push(dab.loc);
+        }
+
+        fn done(&mut self, _world: &mut World, _stroke: &BrushStroke) -> Option<Box<dyn Change>> {
+            Some(Box::new(TraceChange))
+        }
+    }
+
+    struct TraceChange;
+
+    impl Change for TraceChange {}
+
+    // Brush observers and systems without the input plugin, the chord events are triggered by hand
+    fn stroke_app(brush_settings: BrushSettings) -> (App, Entity) {
+        let mut app = App::new();
+        app.insert_resource(WorldPos::new());
+        app.insert_resource(brush_settings);
+        app.insert_resource(BrushAssets{mesh: Handle::default(), material: Handle::default(), radius: 10.0});
+        app.add_message::<BrushStart>();
+        app.add_message::<BrushDone>();
+        app.add_observer(start_brush);
+        app.add_observer(update_brush);
+        app.add_observer(end_brush);
+        app.add_observer(release_brush);
+        app.add_observer(complete_brush);
+        app.add_systems(Update, 
+            (
+                hover_brush,
+                brush_started.run_if(on_message::<BrushStart>.and(resource_exists::<Brush>)),
+                brush_apply.run_if(resource_exists::<Brush>),
+                brush_final.run_if(on_message::<BrushDone>.and(resource_exists::<Brush>))
+            ).chain()
+        );
+        let context = app.world_mut().spawn(BrushSelectController).id();
+        (app, context)
+    }
+
+    fn fire(app: &mut App, context: Entity) {
+        app.world_mut().trigger(Fire::<BrushSelectUpdate>{
+            context, action: context, value: true, state: ActionState::Fired, fired_secs: 0.1, elapsed_secs: 0.1
+        });
+    }
+
+    #[test]
+    fn stroke_begins_off_surface() {
+        let (mut app, context) = stroke_app(BrushSettings::default());
+
+        // Pressed with the cursor off the surface, None to Fired starts and fires in the same frame
+        app.world_mut().trigger(Start::<BrushSelectUpdate>{context, action: context, value: true, state: ActionState::Fired});
+        fire(&mut app, context);
+        app.update();
+        assert!(!app.world().contains_resource::<Brush>());
+
+        // Dragged onto the surface, the hover marker shows up but the stroke doesn't start
+        app.world_mut().resource_mut::<WorldPos>().set(Vec3::new(1.0, 0.0, 1.0));
+        app.update();
+        fire(&mut app, context);
+        app.update();
+        assert!(!app.world().contains_resource::<Brush>());
+
+        // Released, no BrushDone for a stroke that never started
+        app.world_mut().trigger(Cancel::<BrushSelectUpdate>{context, action: context, value: false, state: ActionState::None, elapsed_secs: 0.2});
+        app.update();
+        assert!(app.world().resource::<Messages<BrushDone>>().is_empty());
+        assert!(!app.world().contains_resource::<Brush>());
+    }
+
+    #[test]
+    fn continuous_applies_once_per_frame_when_still() {
+        assert_eq!(run_frames(BrushApplyMode::Continuous, 10, 0.016, 0.0), 10);
+    }
+
+    #[test]
+    fn on_move_applies_only_the_first_dab_when_still() {
+        assert_eq!(run_frames(BrushApplyMode::OnMove, 10, 0.016, 0.0), 1);
+    }
+
+    #[test]
+    fn on_move_applies_at_spacing() {
+        // Spacing is 1.0, the brush moves 0.5 per frame and 4.5 in total
+        assert_eq!(run_frames(BrushApplyMode::OnMove, 10, 0.016, 0.5), 5);
+    }
+
+    #[test]
+    fn fixed_rate_ignores_frame_rate() {
+        // The start dab plus one per 0.1s over the remaining frames
+        assert_eq!(run_frames(BrushApplyMode::FixedRate(10.0), 60, 1.0 / 60.0, 0.0), 10);
+        assert_eq!(run_frames(BrushApplyMode::FixedRate(10.0), 30, 1.0 / 30.0, 0.0), 10);
+    }
+
+    #[test]
+    fn dabs_share_the_elapsed_time() {
+        let (mut brush, _) = test_brush(BrushApplyMode::Continuous);
+        brush.advance(0.1);
+        brush.loc.x = 3.0;
+        let dabs = brush.advance(0.3);
+        assert_eq!(dabs.len(), 3);
+        assert!(dabs.iter().all(|dab| (dab.elapsed - 0.1).abs() < 1e-6));
+    }
+
+    #[test]
+    fn press_again_without_bridging() {
+        let dabs = Arc::new(Mutex::new(Vec::new()));
+        let (mut app, context) = stroke_app(BrushSettings {
+            radius: 4.0,
+            spacing: 0.25,
+            apply_mode: BrushApplyMode::OnMove,
+            typ: Box::new(TraceBrush(dabs.clone())),
+            ..default()
+        });
+        app.world_mut().resource_mut::<WorldPos>().set(Vec3::ZERO);
+        app.world_mut().trigger(Start::<BrushSelectUpdate>{context, action: context, value: true, state: ActionState::Fired});
+        fire(&mut app, context);
+        app.update();
+        assert_eq!(*dabs.lock().unwrap(), vec![Vec3::ZERO]);
+
+        // Mouse released with J held, the chord drops back to Ongoing and the stroke ends
+        app.world_mut().trigger(Ongoing::<BrushSelectUpdate>{
+            context, action: context, value: false, state: ActionState::Ongoing, elapsed_secs: 0.2
+        });
+        app.update();
+        assert!(!app.world().contains_resource::<Brush>());
+
+        // Moved and pressed again, a new stroke begins at the press without dabs in between
+        app.world_mut().resource_mut::<WorldPos>().set(Vec3::new(10.0, 0.0, 0.0));
+        app.update();
+        fire(&mut app, context);
+        app.update();
+        assert_eq!(*dabs.lock().unwrap(), vec![Vec3::ZERO, Vec3::new(10.0, 0.0, 0.0)]);
+    }
+
+    #[test]
+    fn every_stroke_is_recorded() {
+        let dabs = Arc::new(Mutex::new(Vec::new()));
+        let (mut app, context) = stroke_app(BrushSettings {
+            apply_mode: BrushApplyMode::OnMove,
+            typ: Box::new(TraceBrush(dabs.clone())),
+            ..default()
+        });
+        app.insert_resource(Changes::new());
+        app.world_mut().resource_mut::<WorldPos>().set(Vec3::ZERO);
+        app.world_mut().trigger(Start::<BrushSelectUpdate>{context, action: context, value: true, state: ActionState::Fired});
+        fire(&mut app, context);
+        app.update();
+
+        // Both keys released in the same frame, Fired to None completes instead of cancelling
+        app.world_mut().trigger(Complete::<BrushSelectUpdate>{
+            context, action: context, value: false, state: ActionState::None, fired_secs: 0.1, elapsed_secs: 0.1
+        });
+        app.update();
+        assert!(!app.world().contains_resource::<Brush>());
+        assert_eq!(app.world().resource::<Changes>().data.len(), 1);
+
+        // A stroke that ended but wasn't finished yet is finished before the next one replaces it
+        app.world_mut().trigger(Start::<BrushSelectUpdate>{context, action: context, value: true, state: ActionState::Fired});
+        fire(&mut app, context);
+        app.update();
+        app.world_mut().resource_mut::<Brush>().ended = true;
+        fire(&mut app, context);
+        app.world_mut().flush();
+        assert!(!app.world().resource::<Brush>().ended);
+        assert_eq!(app.world().resource::<Changes>().data.len(), 2);
+    }
+}
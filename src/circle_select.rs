@@ -0,0 +1,190 @@
+use bevy::light::{NotShadowCaster, NotShadowReceiver};
+use bevy::ecs::spawn::SpawnWith;
+use bevy::ecs::query::QueryFilter;
+use bevy::prelude::*;
+use bevy_enhanced_input::prelude::{Cancel, Complete};
+use bevy_enhanced_input::prelude::*;
+use bevy::color::palettes::tailwind::*;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::selection::{PASS_THROUGH, ToolKey};
+use crate::world_pos::{UiHover, WorldPos};
+
+// Smaller circles, like a plain click, end without CircleSelectFinal
+const MIN_RADIUS: f32 = 0.1;
+
+pub struct PGEditorCircleSelectPlugin;
+
+impl Plugin for PGEditorCircleSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_input_context::<CircleSelectController>()
+        .add_observer(start_circleselect)
+        .add_observer(update_circleselect)
+        .add_observer(end_circleselect)
+        .add_observer(complete_circleselect)
+        .add_systems(Startup, setup_circleselect_assets)
+        ;
+    }
+}
+
+
+// Unit disc shared by every circle select, scaled by the Transform
+#[derive(Resource)]
+pub struct CircleSelectAssets {
+    pub mesh:     Handle<Mesh>,
+    pub material: Handle<StandardMaterial>
+}
+
+fn setup_circleselect_assets(
+    mut commands:   Commands,
+    mut meshes:     ResMut<Assets<Mesh>>,
+    mut materials:  ResMut<Assets<StandardMaterial>>
+){
+    commands.insert_resource(CircleSelectAssets{
+        mesh:     meshes.add(Circle::new(1.0)),
+        material: materials.add(Color::from(ORANGE_600).with_alpha(0.4))
+    });
+}
+
+
+#[derive(Component, Reflect)]
+pub struct CircleSelectController;
+
+pub fn circle_select_controller() -> impl Bundle {
+    return (
+        CircleSelectController,
+        Actions::<CircleSelectController>::spawn(
+            SpawnWith(|context: &mut ActionSpawner<_>| {
+            let member1 = context
+                .spawn((Action::<CircleSelectUpdate1>::new(), Down::default(), PASS_THROUGH, ToolKey, bindings![KeyCode::KeyC]))
+                .id();
+            let member2 = context
+                .spawn((Action::<CircleSelectUpdate2>::new(), Down::default(), PASS_THROUGH, bindings![MouseButton::Right]))
+                .id();
+            context.spawn((Action::<CircleSelectUpdate>::new(), Chord::new([member1, member2])));
+
+            }))
+        );
+}
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct CircleSelectUpdate1;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct CircleSelectUpdate2;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct CircleSelectUpdate;
+
+
+#[derive(Event)]
+pub struct CircleSelectFinal {
+    center: Vec3,
+    radius: f32
+}
+impl CircleSelectFinal {
+    pub fn center(&self) -> Vec3 {
+        self.center
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    // Point on the XZ plane
+    pub fn has_point(&self, loc: Vec2) -> bool {
+        self.center.xz().distance_squared(loc) <= self.radius * self.radius
+    }
+
+    // Entities whose origin is inside the circle
+    pub fn query_entities<F: QueryFilter>(&self, query: &Query<(Entity, &GlobalTransform), F>) -> Vec<Entity> {
+        query
+            .iter()
+            .filter(|(_, transform)| self.has_point(transform.translation().xz()))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+}
+
+
+#[derive(Component, Debug, Default)]
+pub struct CircleSelect {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+
+fn start_circleselect(
+    _trigger:       On<Start<CircleSelectUpdate>>,
+    mut commands:   Commands,
+    assets:         Res<CircleSelectAssets>,
+    input_data:     Res<WorldPos>,
+    ui_hover:       UiHover,
+    circles:        Query<Entity, With<CircleSelect>>
+){
+    // Leftovers from a circle that never ended
+    for cs_entity in circles.iter(){
+        commands.entity(cs_entity).despawn();
+    }
+    if ui_hover.any() {
+        return;
+    }
+    let Some(world_pos) = input_data.get() else {return;};
+    commands.spawn((
+        Mesh3d(assets.mesh.clone()),
+        MeshMaterial3d(assets.material.clone()),
+        Transform::from_xyz(world_pos.x, world_pos.y + 0.1, world_pos.z)
+                  .with_rotation(Quat::from_rotation_x(-FRAC_PI_2))
+                  .with_scale(Vec3::ZERO),
+        CircleSelect{center: world_pos, radius: 0.0},
+        NotShadowCaster,
+        NotShadowReceiver
+    ));
+}
+
+fn update_circleselect(
+    _trigger:   On<Fire<CircleSelectUpdate>>,
+    input_data: Res<WorldPos>,
+    query:      Single<(&mut Transform, &mut CircleSelect)>
+){
+    let Some(world_pos) = input_data.get() else {return;};
+    let (mut transform, mut circle_select) = query.into_inner();
+    let radius = circle_select.center.xz().distance(world_pos.xz());
+    if radius != circle_select.radius {
+        circle_select.radius = radius;
+        transform.scale = Vec3::splat(radius);
+    }
+}
+
+fn finish_circleselect(
+    commands:   &mut Commands,
+    query:      &Query<(Entity, &CircleSelect)>
+){
+    for (cs_entity, circle_select) in query.iter(){
+        if circle_select.radius >= MIN_RADIUS {
+            commands.trigger(CircleSelectFinal{center: circle_select.center, radius: circle_select.radius});
+        }
+        commands.entity(cs_entity).despawn();
+    }
+}
+
+fn end_circleselect(
+    _trigger:       On<Cancel<CircleSelectUpdate>>,
+    mut commands:   Commands,
+    query:          Query<(Entity, &CircleSelect)>
+){
+    finish_circleselect(&mut commands, &query);
+}
+
+// Both keys released in the same frame, Fired to None completes instead of cancelling
+fn complete_circleselect(
+    _trigger:       On<Complete<CircleSelectUpdate>>,
+    mut commands:   Commands,
+    query:          Query<(Entity, &CircleSelect)>
+){
+    finish_circleselect(&mut commands, &query);
+}
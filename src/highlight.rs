@@ -0,0 +1,140 @@
+use bevy::camera::primitives::Aabb;
+use bevy::picking::mesh_picking::ray_cast::MeshRayCast;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy::color::palettes::tailwind::*;
+
+use crate::box_select::AABB;
+use crate::selection::{Selection, Selectable, PreviewSelected, cursor_ray, pick_selectable};
+use crate::world_pos::EditorCamera;
+
+
+pub struct PGEditorSelectionHighlightPlugin;
+
+impl Plugin for PGEditorSelectionHighlightPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<SelectionHighlightSettings>()
+        .init_resource::<Hovered>()
+        .add_systems(Update,
+            (
+                update_hovered,
+                draw_highlight
+            ).chain()
+        )
+        ;
+    }
+}
+
+
+#[derive(Resource, Clone, Debug)]
+pub struct SelectionHighlightSettings {
+    pub selected_color: Color,
+    pub hover_color:    Color,
+    pub preview_color:  Color,
+    pub hover:          bool,
+    pub padding:        f32     // Grows the boxes so they don't z-fight with the meshes
+}
+impl Default for SelectionHighlightSettings {
+    fn default() -> Self {
+        Self {
+            selected_color: Color::from(ORANGE_500),
+            hover_color:    Color::from(SKY_300),
+            preview_color:  Color::from(AMBER_200),
+            hover:          true,
+            padding:        0.05
+        }
+    }
+}
+
+// Selectable entity under the cursor
+#[derive(Resource, Default, Debug)]
+pub struct Hovered {
+    entity: Option<Entity>
+}
+impl Hovered {
+    pub fn get(&self) -> Option<Entity> {
+        self.entity
+    }
+}
+
+
+// World bounds of the entity and all of its descendants
+pub fn world_bounds(
+    entity:   Entity,
+    bounds:   &Query<(&GlobalTransform, Option<&Aabb>)>,
+    children: &Query<&Children>
+) -> Option<AABB> {
+    let mut merged: Option<AABB> = None;
+    for e in std::iter::once(entity).chain(children.iter_descendants(entity)) {
+        let Ok((transform, Some(aabb))) = bounds.get(e) else {continue;};
+        let b = AABB::from_bounds(transform, aabb);
+        merged = Some(match merged {
+            Some(m) => AABB {
+                min_x: m.min_x.min(b.min_x),
+                max_x: m.max_x.max(b.max_x),
+                min_y: m.min_y.min(b.min_y),
+                max_y: m.max_y.max(b.max_y),
+                min_z: m.min_z.min(b.min_z),
+                max_z: m.max_z.max(b.max_z)
+            },
+            None => b
+        });
+    }
+    merged
+}
+
+fn update_hovered(
+    mut hovered:    ResMut<Hovered>,
+    mut ray_cast:   MeshRayCast,
+    settings:       Res<SelectionHighlightSettings>,
+    window:         Query<&Window, With<PrimaryWindow>>,
+    cameras:        Query<(&Camera, &GlobalTransform, Has<EditorCamera>)>,
+    selectable:     Query<(), With<Selectable>>,
+    parents:        Query<&ChildOf>
+){
+    let entity = match settings.hover {
+        true => cursor_ray(&window, &cameras).and_then(|ray| pick_selectable(ray, &mut ray_cast, &selectable, &parents)),
+        false => None
+    };
+    if hovered.entity != entity {
+        hovered.entity = entity;
+    }
+}
+
+fn draw_highlight(
+    mut gizmos: Gizmos,
+    settings:   Res<SelectionHighlightSettings>,
+    selection:  Option<Res<Selection>>,
+    hovered:    Res<Hovered>,
+    previewed:  Query<Entity, With<PreviewSelected>>,
+    bounds:     Query<(&GlobalTransform, Option<&Aabb>)>,
+    children:   Query<&Children>
+){
+    let mut draw = |entity: Entity, color: Color| {
+        let transform = match world_bounds(entity, &bounds, &children) {
+            Some(aabb) => {
+                let (min, max) = (aabb.min(), aabb.max());
+                Transform::from_translation((min + max) / 2.0)
+                    .with_scale(max - min + Vec3::splat(settings.padding * 2.0))
+            }
+            None => {
+                let Ok((transform, _)) = bounds.get(entity) else {return;};
+                Transform::from_translation(transform.translation()).with_scale(Vec3::splat(0.5))
+            }
+        };
+        gizmos.cuboid(transform, color);
+    };
+
+    if let Some(selection) = selection {
+        for entity in selection.iter(){
+            draw(*entity, settings.selected_color);
+        }
+    }
+    for entity in previewed.iter(){
+        draw(entity, settings.preview_color);
+    }
+    if let Some(entity) = hovered.get() {
+        draw(entity, settings.hover_color);
+    }
+}
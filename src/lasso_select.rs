@@ -0,0 +1,358 @@
+use bevy::ecs::spawn::SpawnWith;
+use bevy::ecs::query::QueryFilter;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_enhanced_input::prelude::{Cancel, Complete, Press};
+use bevy_enhanced_input::prelude::*;
+use bevy::color::palettes::tailwind::*;
+
+use crate::selection::{PASS_THROUGH, ToolKey};
+use crate::world_pos::{UiHover, WorldPos};
+
+// Minimal XZ distance between two lasso points
+const LASSO_STEP: f32 = 0.25;
+
+
+pub struct PGEditorLassoSelectPlugin;
+
+impl Plugin for PGEditorLassoSelectPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .add_input_context::<LassoSelectController>()
+        .add_input_context::<PolygonSelectController>()
+        .add_observer(start_lasso)
+        .add_observer(update_lasso)
+        .add_observer(end_lasso)
+        .add_observer(complete_lasso)
+        .add_observer(add_polygon_point)
+        .add_observer(close_polygon)
+        .add_observer(abort_polygon)
+        .add_systems(Update,
+            (
+                draw_lasso.run_if(any_with_component::<LassoSelect>),
+                draw_polygon.run_if(any_with_component::<PolygonSelect>)
+            )
+        )
+        ;
+    }
+}
+
+
+#[derive(Component, Reflect)]
+pub struct LassoSelectController;
+
+pub fn lasso_select_controller() -> impl Bundle {
+    return (
+        LassoSelectController,
+        Actions::<LassoSelectController>::spawn(
+            SpawnWith(|context: &mut ActionSpawner<_>| {
+            let member1 = context
+                .spawn((Action::<LassoSelectUpdate1>::new(), Down::default(), PASS_THROUGH, ToolKey, bindings![KeyCode::KeyL]))
+                .id();
+            let member2 = context
+                .spawn((Action::<LassoSelectUpdate2>::new(), Down::default(), PASS_THROUGH, bindings![MouseButton::Left]))
+                .id();
+            context.spawn((Action::<LassoSelectUpdate>::new(), Chord::new([member1, member2])));
+
+            }))
+        );
+}
+
+#[derive(Component, Reflect)]
+pub struct PolygonSelectController;
+
+pub fn polygon_select_controller() -> impl Bundle {
+    return (
+        PolygonSelectController,
+        Actions::<PolygonSelectController>::spawn(
+            SpawnWith(|context: &mut ActionSpawner<_>| {
+            let member1 = context
+                .spawn((Action::<PolygonSelectAdd1>::new(), Down::default(), PASS_THROUGH, ToolKey, bindings![KeyCode::KeyP]))
+                .id();
+            let member2 = context
+                .spawn((Action::<PolygonSelectAdd2>::new(), Press::default(), PASS_THROUGH, bindings![MouseButton::Left]))
+                .id();
+            context.spawn((Action::<PolygonSelectAdd>::new(), Chord::new([member1, member2])));
+            context.spawn((Action::<PolygonSelectClose>::new(), Press::default(), bindings![KeyCode::Enter]));
+            context.spawn((Action::<PolygonSelectAbort>::new(), Press::default(), PASS_THROUGH, bindings![KeyCode::Escape]));
+
+            }))
+        );
+}
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct LassoSelectUpdate1;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct LassoSelectUpdate2;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct LassoSelectUpdate;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct PolygonSelectAdd1;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct PolygonSelectAdd2;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct PolygonSelectAdd;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct PolygonSelectClose;
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct PolygonSelectAbort;
+
+
+// Outline of a lasso or polygon selection, both on the ground and on screen
+#[derive(Clone, Debug, Default)]
+pub struct SelectPolygon {
+    pub points:        Vec<Vec3>,
+    pub screen_points: Vec<Vec2>
+}
+
+impl SelectPolygon {
+    // Points without a screen position are dropped so both outlines stay the same polygon
+    fn push(&mut self, loc: Vec3, screen_loc: Option<Vec2>){
+        let Some(screen_loc) = screen_loc else {return;};
+        self.points.push(loc);
+        self.screen_points.push(screen_loc);
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // Point on the XZ plane
+    pub fn has_point(&self, loc: Vec2) -> bool {
+        polygon_has_point(&self.points, |p| p.xz(), loc)
+    }
+
+    // Point in viewport coordinates
+    pub fn has_screen_point(&self, loc: Vec2) -> bool {
+        polygon_has_point(&self.screen_points, |p| *p, loc)
+    }
+
+    // World point projected through the camera, tested in screen space
+    pub fn has_world_point(&self, camera: &Camera, camera_transform: &GlobalTransform, loc: Vec3) -> bool {
+        let Ok(screen_loc) = camera.world_to_viewport(camera_transform, loc) else {return false;};
+        self.has_screen_point(screen_loc)
+    }
+}
+
+// Even-odd rule
+fn polygon_has_point<T>(points: &[T], to_2d: impl Fn(&T) -> Vec2, loc: Vec2) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let (a, b) = (to_2d(&points[i]), to_2d(&points[j]));
+        if (a.y > loc.y) != (b.y > loc.y) && loc.x < (b.x - a.x) * (loc.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+
+// Triggered by both lasso and polygon tools
+#[derive(Event)]
+pub struct PolygonSelectFinal {
+    polygon: SelectPolygon
+}
+impl PolygonSelectFinal {
+    pub fn polygon(&self) -> &SelectPolygon {
+        &self.polygon
+    }
+
+    pub fn has_point(&self, loc: Vec2) -> bool {
+        self.polygon.has_point(loc)
+    }
+
+    pub fn has_screen_point(&self, loc: Vec2) -> bool {
+        self.polygon.has_screen_point(loc)
+    }
+
+    pub fn has_world_point(&self, camera: &Camera, camera_transform: &GlobalTransform, loc: Vec3) -> bool {
+        self.polygon.has_world_point(camera, camera_transform, loc)
+    }
+
+    // Entities whose origin is inside the polygon on the XZ plane
+    pub fn query_entities<F: QueryFilter>(&self, query: &Query<(Entity, &GlobalTransform), F>) -> Vec<Entity> {
+        query
+            .iter()
+            .filter(|(_, transform)| self.has_point(transform.translation().xz()))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+}
+
+
+#[derive(Component, Debug, Default)]
+pub struct LassoSelect {
+    pub polygon: SelectPolygon
+}
+
+#[derive(Component, Debug, Default)]
+pub struct PolygonSelect {
+    pub polygon: SelectPolygon
+}
+
+
+fn cursor_position(window: &Query<&Window, With<PrimaryWindow>>) -> Option<Vec2> {
+    window.single().ok().and_then(|window| window.cursor_position())
+}
+
+fn start_lasso(
+    _trigger:       On<Start<LassoSelectUpdate>>,
+    mut commands:   Commands,
+    input_data:     Res<WorldPos>,
+    ui_hover:       UiHover,
+    window:         Query<&Window, With<PrimaryWindow>>,
+    lassos:         Query<Entity, With<LassoSelect>>
+){
+    // Leftovers from a lasso that never ended
+    for lasso_entity in lassos.iter(){
+        commands.entity(lasso_entity).despawn();
+    }
+    if ui_hover.any() {
+        return;
+    }
+    let Some(world_pos) = input_data.get() else {return;};
+    let mut lasso = LassoSelect::default();
+    lasso.polygon.push(world_pos, cursor_position(&window));
+    commands.spawn(lasso);
+}
+
+fn update_lasso(
+    _trigger:   On<Fire<LassoSelectUpdate>>,
+    input_data: Res<WorldPos>,
+    window:     Query<&Window, With<PrimaryWindow>>,
+    mut lasso:  Single<&mut LassoSelect>
+){
+    let Some(world_pos) = input_data.get() else {return;};
+    if let Some(last) = lasso.polygon.points.last() {
+        if last.xz().distance(world_pos.xz()) < LASSO_STEP {
+            return;
+        }
+    }
+    lasso.polygon.push(world_pos, cursor_position(&window));
+}
+
+fn finish_lasso(
+    commands:   &mut Commands,
+    query:      &Query<(Entity, &LassoSelect)>
+){
+    for (lasso_entity, lasso) in query.iter(){
+        if lasso.polygon.len() >= 3 {
+            commands.trigger(PolygonSelectFinal{polygon: lasso.polygon.clone()});
+        }
+        commands.entity(lasso_entity).despawn();
+    }
+}
+
+fn end_lasso(
+    _trigger:       On<Cancel<LassoSelectUpdate>>,
+    mut commands:   Commands,
+    query:          Query<(Entity, &LassoSelect)>
+){
+    finish_lasso(&mut commands, &query);
+}
+
+// Both keys released in the same frame, Fired to None completes instead of cancelling
+fn complete_lasso(
+    _trigger:       On<Complete<LassoSelectUpdate>>,
+    mut commands:   Commands,
+    query:          Query<(Entity, &LassoSelect)>
+){
+    finish_lasso(&mut commands, &query);
+}
+
+fn add_polygon_point(
+    _trigger:       On<Start<PolygonSelectAdd>>,
+    mut commands:   Commands,
+    input_data:     Res<WorldPos>,
+    ui_hover:       UiHover,
+    window:         Query<&Window, With<PrimaryWindow>>,
+    mut polygons:   Query<&mut PolygonSelect>
+){
+    if ui_hover.any() {
+        return;
+    }
+    let Some(world_pos) = input_data.get() else {return;};
+    let screen_pos = cursor_position(&window);
+    if let Ok(mut polygon) = polygons.single_mut() {
+        polygon.polygon.push(world_pos, screen_pos);
+    } else {
+        let mut polygon = PolygonSelect::default();
+        polygon.polygon.push(world_pos, screen_pos);
+        commands.spawn(polygon);
+    }
+}
+
+fn close_polygon(
+    _trigger:       On<Start<PolygonSelectClose>>,
+    mut commands:   Commands,
+    query:          Single<(Entity, &PolygonSelect)>
+){
+    let (polygon_entity, polygon) = query.into_inner();
+    if polygon.polygon.len() >= 3 {
+        commands.trigger(PolygonSelectFinal{polygon: polygon.polygon.clone()});
+    }
+    commands.entity(polygon_entity).despawn();
+}
+
+fn abort_polygon(
+    _trigger:       On<Start<PolygonSelectAbort>>,
+    mut commands:   Commands,
+    polygons:       Query<Entity, With<PolygonSelect>>
+){
+    for polygon_entity in polygons.iter(){
+        commands.entity(polygon_entity).despawn();
+    }
+}
+
+fn draw_lasso(
+    mut gizmos: Gizmos,
+    lassos:     Query<&LassoSelect>
+){
+    for lasso in lassos.iter(){
+        let color = Color::from(ORANGE_600);
+        gizmos.linestrip(lasso.polygon.points.iter().map(|p| *p + Vec3::Y * 0.1), color);
+        if let (Some(first), Some(last)) = (lasso.polygon.points.first(), lasso.polygon.points.last()) {
+            gizmos.line(*last + Vec3::Y * 0.1, *first + Vec3::Y * 0.1, color.with_alpha(0.4));
+        }
+    }
+}
+
+fn draw_polygon(
+    mut gizmos: Gizmos,
+    input_data: Res<WorldPos>,
+    polygons:   Query<&PolygonSelect>
+){
+    for polygon in polygons.iter(){
+        let color = Color::from(ORANGE_600);
+        gizmos.linestrip(polygon.polygon.points.iter().map(|p| *p + Vec3::Y * 0.1), color);
+        let (Some(first), Some(last)) = (polygon.polygon.points.first(), polygon.polygon.points.last()) else {continue;};
+        if let Some(world_pos) = input_data.get() {
+            gizmos.line(*last + Vec3::Y * 0.1, world_pos + Vec3::Y * 0.1, color);
+            gizmos.line(world_pos + Vec3::Y * 0.1, *first + Vec3::Y * 0.1, color.with_alpha(0.4));
+        }
+    }
+}
@@ -14,15 +14,36 @@ impl Plugin for PGEditorToolsPlugin {
 }
 
 pub mod box_select;
+pub mod brush_registry;
+pub mod brush_ui;
 pub mod brushes;
+pub mod circle_select;
+pub mod highlight;
+pub mod lasso_select;
+pub mod paint;
+pub mod scatter;
+pub mod sculpt;
+pub mod selection;
 pub mod tracker;
 pub mod world_pos;
 
 
 pub mod prelude {
     pub use crate::tracker::{PGEditorTrackerPlugin, Undo, Redo, UndoMessage, RedoMessage, Changes, Change, ChangesSet};
-    pub use crate::box_select::{BoxSelectController, box_select_controller, box_select_changed, BoxSelectFinal, BoxSelect, PGEditorBoxSelectPlugin};
-    pub use crate::brushes::{BrushSelectController, brush_select_controller, brush_changed, BrushDone, BrushStart, Brush, PGEditorBrushSelectPlugin, BrushType, BrushSettings};
-    pub use crate::world_pos::WorldPos;
+    pub use crate::box_select::{BoxSelectController, box_select_controller, box_select_controller_with, box_select_changed, BoxSelectFinal, BoxSelectPreview, BoxSelect, 
+                                PGEditorBoxSelectPlugin, SelectPolicy, AABB, BoxSelectSettings, BoxSelectStyle, BoxSelectState, BoxSelectAssets};
+    pub use crate::lasso_select::{LassoSelectController, lasso_select_controller, PolygonSelectController, polygon_select_controller, 
+                                  LassoSelect, PolygonSelect, PolygonSelectFinal, SelectPolygon, PGEditorLassoSelectPlugin};
+    pub use crate::circle_select::{CircleSelectController, circle_select_controller, CircleSelectFinal, CircleSelect, CircleSelectAssets, PGEditorCircleSelectPlugin};
+    pub use crate::selection::{PGEditorSelectionPlugin, Selection, Selectable, PreviewSelected, SelectMode, ChangeSelection, PickSelectController, pick_select_controller};
+    pub use crate::highlight::{PGEditorSelectionHighlightPlugin, SelectionHighlightSettings, Hovered, world_bounds};
+    pub use crate::brushes::{BrushSelectController, brush_select_controller, brush_changed, BrushDone, BrushStart, Brush, PGEditorBrushSelectPlugin, BrushType, BrushSettings, BrushAssets, 
+                             BrushCursor, BrushSurface, sample_surface, BrushFalloff, BrushDab, BrushApplyMode, BrushParam, BrushParamValue, BrushStroke};
+    pub use crate::brush_registry::{PGEditorBrushRegistryPlugin, BrushRegistry, RegisteredBrush, SetActiveBrush, BrushHotkeysController, brush_hotkeys_controller, BrushSlot};
+    pub use crate::brush_ui::{PGEditorBrushUiPlugin, BrushPanel};
+    pub use crate::paint::{PaintBrush, ChangePaint, splat_image};
+    pub use crate::scatter::{ScatterBrush, ScatterPrefab, Scattered, ChangeScatter};
+    pub use crate::sculpt::{SculptBrush, SculptOp, ChangeSculpt};
+    pub use crate::world_pos::{WorldPos, EditorCamera, UiHover};
     pub use crate::PGEditorToolsPlugin;
 }
\ No newline at end of file
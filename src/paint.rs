@@ -0,0 +1,267 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::brushes::{BrushDab, BrushParam, BrushParamValue, BrushStroke, BrushType};
+use crate::tracker::{Change, Changes};
+
+
+// Splat map with every texel set to `weights`, kept in the main world so it can be painted
+pub fn splat_image(width: u32, height: u32, weights: [u8; 4]) -> Image {
+    Image::new_fill(
+        Extent3d{width, height, depth_or_array_layers: 1},
+        TextureDimension::D2,
+        &weights,
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD
+    )
+}
+
+// Paints RGBA weights into an Rgba8 image stretched over `bounds` on the XZ plane,
+// bounds.min is at uv (0, 0) and bounds.max at uv (1, 1)
+#[derive(Clone)]
+pub struct PaintBrush {
+    pub image:   Handle<Image>,
+    pub bounds:  Rect,
+    pub weights: Vec4,      // Texel value the brush blends towards, a single layer for splat maps
+    pub rate:    f32,       // Blend per second at full weight
+    before:      Option<Vec<u8>>
+}
+
+impl PaintBrush {
+    pub fn new(image: Handle<Image>, bounds: Rect, weights: Vec4) -> Self {
+        PaintBrush {
+            image,
+            bounds,
+            weights,
+            rate: 5.0,
+            before: None
+        }
+    }
+
+    // Splat map brush painting layer 0..4 (RGBA)
+    pub fn layer(image: Handle<Image>, bounds: Rect, layer: usize) -> Self {
+        let mut weights = Vec4::ZERO;
+        weights[layer.min(3)] = 1.0;
+        PaintBrush::new(image, bounds, weights)
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    fn world_to_texel(&self, loc: Vec2, size: UVec2) -> Vec2 {
+        (loc - self.bounds.min) / self.bounds.size() * size.as_vec2()
+    }
+
+    fn texel_to_world(&self, texel: UVec2, size: UVec2) -> Vec2 {
+        self.bounds.min + (texel.as_vec2() + 0.5) / size.as_vec2() * self.bounds.size()
+    }
+
+    // Texels under the dab clamped to the image, None if the dab misses it
+    fn texel_rect(&self, dab: &BrushDab, size: UVec2) -> Option<(UVec2, UVec2)> {
+        let center = dab.loc.xz();
+        let min = self.world_to_texel(center - dab.radius, size).floor().max(Vec2::ZERO).as_uvec2();
+        let max = self.world_to_texel(center + dab.radius, size).ceil().max(Vec2::ZERO).as_uvec2().min(size);
+        (min.x < max.x && min.y < max.y).then_some((min, max))
+    }
+
+    // Blends the texels under the dab, true if any of them changed.
+    // Inverted, the brush layers are removed and the rest scaled back up to the same sum
+    fn paint(&self, data: &mut [u8], size: UVec2, dab: &BrushDab, invert: bool) -> bool {
+        let Some((min, max)) = self.texel_rect(dab, size) else {return false;};
+        let amount = self.rate * dab.elapsed;
+        let mut painted = false;
+
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let loc = self.texel_to_world(UVec2::new(x, y), size);
+                let weight = dab.weight(Vec3::new(loc.x, dab.loc.y, loc.y));
+                if weight <= 0.0 {
+                    continue;
+                }
+                let i = ((y * size.x + x) * 4) as usize;
+                let Some(texel) = data.get_mut(i..i + 4) else {continue;};
+                let current = Vec4::from_array([texel[0], texel[1], texel[2], texel[3]].map(|c| c as f32 / 255.0));
+                let target = match invert {
+                    true => {
+                        let rest = current * (Vec4::ONE - self.weights);
+                        if rest.element_sum() > 0.0 {rest * current.element_sum() / rest.element_sum()} else {current}
+                    }
+                    false => self.weights
+                };
+                let value = current.lerp(target, (weight * amount).min(1.0));
+                let value = (value * 255.0).round().clamp(Vec4::ZERO, Vec4::splat(255.0)).to_array().map(|c| c as u8);
+                if texel != value {
+                    texel.copy_from_slice(&value);
+                    painted = true;
+                }
+            }
+        }
+        painted
+    }
+}
+
+// Weights are blended linearly, an Srgb image would skew them on the GPU
+fn paintable(image: &Image) -> bool {
+    image.texture_descriptor.format == TextureFormat::Rgba8Unorm
+}
+
+const PAINT_LAYERS: [&str; 4] = ["Red", "Green", "Blue", "Alpha"];
+
+impl BrushType for PaintBrush {
+    fn name(&self) -> &str {
+        "Paint"
+    }
+
+    fn params(&self) -> Vec<BrushParam> {
+        vec![
+            BrushParam::Dropdown{name: "layer", value: self.weights.max_position(), options: PAINT_LAYERS.to_vec()},
+            BrushParam::Slider{name: "rate", value: self.rate, min: 0.0, max: 20.0}
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: BrushParamValue) {
+        match (name, value) {
+            ("layer", BrushParamValue::Choice(i)) => {
+                self.weights = Vec4::ZERO;
+                self.weights[i.min(3)] = 1.0;
+            }
+            ("rate", BrushParamValue::Float(v)) => self.rate = v,
+            _ => {}
+        }
+    }
+
+    fn started(&mut self, world: &mut World, _stroke: &BrushStroke) {
+        self.before = None;
+        let Some(mut images) = world.get_resource_mut::<Assets<Image>>() else {return;};
+        let Some(image) = images.get_mut(&self.image) else {return;};
+        if !paintable(image) {
+            warn!("PaintBrush only supports Rgba8Unorm images, got {:?}", image.texture_descriptor.format);
+            return;
+        }
+        if image.data.is_none() {
+            warn!("PaintBrush image has no data in the main world, create it with RenderAssetUsages::MAIN_WORLD");
+            return;
+        }
+        // Keeps the data around after the next upload to the GPU
+        image.asset_usage = RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD;
+        self.before = image.data.clone();
+    }
+
+    fn apply(&mut self, world: &mut World, dab: &BrushDab, stroke: &BrushStroke) {
+        if self.before.is_none() || self.bounds.is_empty() {
+            return;
+        }
+        let Some(mut images) = world.get_resource_mut::<Assets<Image>>() else {return;};
+        // Checked before get_mut, which marks the image for upload
+        let Some(image) = images.get(&self.image) else {return;};
+        let size = image.size();
+        if self.texel_rect(dab, size).is_none() {
+            return;
+        }
+        let Some(image) = images.get_mut(&self.image) else {return;};
+        let Some(data) = image.data.as_mut() else {return;};
+        self.paint(data, size, dab, stroke.invert());
+    }
+
+    fn done(&mut self, world: &mut World, _stroke: &BrushStroke) -> Option<Box<dyn Change>> {
+        let before = self.before.take()?;
+        let after = world
+            .get_resource::<Assets<Image>>()
+            .and_then(|images| images.get(&self.image))
+            .and_then(|image| image.data.clone())?;
+        if after == before {
+            return None;
+        }
+        Some(Box::new(ChangePaint{image: self.image.clone(), before, after}))
+    }
+}
+
+
+// Image data before and after the stroke
+#[derive(Clone)]
+pub struct ChangePaint {
+    image:  Handle<Image>,
+    before: Vec<u8>,
+    after:  Vec<u8>
+}
+
+impl ChangePaint {
+    fn restore(&self, world: &mut World, data: Vec<u8>) {
+        let Some(mut images) = world.get_resource_mut::<Assets<Image>>() else {return;};
+        if let Some(image) = images.get_mut(&self.image) {
+            image.data = Some(data);
+        }
+    }
+}
+
+impl Change for ChangePaint {
+    fn undo(
+        &mut self,
+        world:      &mut World
+    ) {
+        self.restore(world, self.before.clone());
+    }
+
+    fn redo(
+        &mut self,
+        world: &mut World
+    ) {
+        self.restore(world, self.after.clone());
+    }
+
+    fn record(
+        &self,
+        changes: &mut ResMut<Changes>
+    ) {
+        changes.record(Box::new(self.clone()));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brushes::BrushFalloff;
+
+    fn brush() -> PaintBrush {
+        PaintBrush::layer(Handle::default(), Rect::new(-10.0, -10.0, 10.0, 10.0), 1)
+    }
+
+    fn dab(loc: Vec3) -> BrushDab {
+        BrushDab{elapsed: 0.1, loc, radius: 2.0, strength: 1.0, hardness: 0.0, falloff: BrushFalloff::Constant}
+    }
+
+    #[test]
+    fn world_to_texel() {
+        let brush = brush();
+        let size = UVec2::new(20, 10);
+        assert_eq!(brush.world_to_texel(Vec2::new(-10.0, -10.0), size), Vec2::ZERO);
+        assert_eq!(brush.world_to_texel(Vec2::ZERO, size), Vec2::new(10.0, 5.0));
+        assert_eq!(brush.world_to_texel(Vec2::new(10.0, 10.0), size), Vec2::new(20.0, 10.0));
+        assert_eq!(brush.texel_to_world(UVec2::new(10, 5), size), Vec2::new(0.5, 1.0));
+    }
+
+    #[test]
+    fn paint_under_the_dab() {
+        let brush = brush().with_rate(5.0);
+        let size = UVec2::new(20, 20);
+        let mut data = splat_image(20, 20, [255, 0, 0, 0]).data.unwrap();
+
+        // Half way towards green at the center, nothing outside the radius
+        assert!(brush.paint(&mut data, size, &dab(Vec3::ZERO), false));
+        let texel = |data: &[u8], x: usize, y: usize| data[(y * 20 + x) * 4..(y * 20 + x) * 4 + 4].to_vec();
+        assert_eq!(texel(&data, 10, 10), vec![128, 128, 0, 0]);
+        assert_eq!(texel(&data, 15, 10), vec![255, 0, 0, 0]);
+
+        // Inverted, green goes back to red
+        assert!(brush.paint(&mut data, size, &dab(Vec3::ZERO), true));
+        assert_eq!(texel(&data, 10, 10), vec![192, 64, 0, 0]);
+
+        // Off the image
+        assert!(brush.texel_rect(&dab(Vec3::new(50.0, 0.0, 0.0)), size).is_none());
+        assert!(!brush.paint(&mut data, size, &dab(Vec3::new(50.0, 0.0, 0.0)), false));
+    }
+}
@@ -0,0 +1,295 @@
+use bevy::ecs::system::SystemState;
+use bevy::picking::mesh_picking::ray_cast::MeshRayCast;
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::brushes::{BrushDab, BrushParam, BrushParamValue, BrushStroke, BrushSurface, BrushType, sample_surface};
+use crate::tracker::{Change, Changes};
+
+static NEXT_INSTANCE_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Debug)]
+pub enum ScatterPrefab {
+    Scene(Handle<Scene>),
+    Mesh{mesh: Handle<Mesh>, material: Handle<StandardMaterial>}
+}
+
+// Root instance placed by a ScatterBrush, only these are removed in erase mode.
+// The id survives undo and redo, unlike the entity
+#[derive(Component, Clone, Debug)]
+pub struct Scattered {
+    pub id:     u64,
+    pub prefab: ScatterPrefab
+}
+
+#[derive(Clone, Debug)]
+struct ScatterInstance {
+    id:        u64,
+    prefab:    ScatterPrefab,
+    transform: Transform
+}
+
+impl ScatterInstance {
+    fn new(prefab: ScatterPrefab, transform: Transform) -> Self {
+        ScatterInstance{id: NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed), prefab, transform}
+    }
+
+    fn spawn(&self, world: &mut World) -> Entity {
+        let mut entity = world.spawn((self.transform, Scattered{id: self.id, prefab: self.prefab.clone()}));
+        match &self.prefab {
+            ScatterPrefab::Scene(scene) => {
+                entity.insert(SceneRoot(scene.clone()));
+            }
+            ScatterPrefab::Mesh{mesh, material} => {
+                entity.insert((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone())));
+            }
+        }
+        entity.id()
+    }
+}
+
+// Xorshift, good enough for placement and keeps strokes reproducible for a seed and stroke id
+#[derive(Clone, Debug)]
+struct ScatterRng(u32);
+
+impl ScatterRng {
+    // Brush::new clones the brush for every stroke, so the state is mixed with the stroke id
+    // instead of carried over, otherwise every stroke would replay the same layout
+    fn for_stroke(seed: u32, stroke: u64) -> Self {
+        let mut h = seed ^ (stroke as u32).wrapping_mul(0x85eb_ca6b) ^ ((stroke >> 32) as u32);
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x7feb_352d);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x846c_a68b);
+        h ^= h >> 16;
+        ScatterRng(h.max(1))
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    fn range(&mut self, range: Vec2) -> f32 {
+        range.x + (range.y - range.x) * self.next()
+    }
+}
+
+
+#[derive(Clone)]
+pub struct ScatterBrush {
+    pub prefabs:     Vec<ScatterPrefab>,
+    pub density:     f32,   // Instances per square unit for each dab at full weight
+    pub min_spacing: f32,   // Poisson disk radius between any two instances on XZ
+    pub scale:       Vec2,  // Uniform scale range
+    pub rotation:    Vec2,  // Yaw range in radians
+    pub align:       bool,  // Tilt instances to the BrushSurface normal
+    pub erase:       bool,
+    seed:            u32,
+    rng:             ScatterRng,
+    spawned:         Vec<ScatterInstance>,
+    erased:          Vec<ScatterInstance>
+}
+
+impl ScatterBrush {
+    pub fn new(prefabs: Vec<ScatterPrefab>) -> Self {
+        ScatterBrush {
+            prefabs,
+            density: 0.05,
+            min_spacing: 2.0,
+            scale: Vec2::new(0.8, 1.2),
+            rotation: Vec2::new(0.0, TAU),
+            align: false,
+            erase: false,
+            seed: 0x9e37_79b9,
+            rng: ScatterRng(0x9e37_79b9),
+            spawned: Vec::new(),
+            erased: Vec::new()
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self.rng = ScatterRng(seed.max(1));
+        self
+    }
+
+    fn scatter(&mut self, world: &mut World, dab: &BrushDab) {
+        if self.prefabs.is_empty() {
+            return;
+        }
+        // Transform rather than GlobalTransform, instances from earlier dabs this frame aren't propagated yet
+        let mut taken: Vec<Vec2> = world
+            .query_filtered::<&Transform, With<Scattered>>()
+            .iter(world)
+            .map(|transform| transform.translation.xz())
+            .collect();
+        let attempts = (self.density * std::f32::consts::PI * dab.radius * dab.radius * dab.strength).round() as usize;
+        let spacing = self.min_spacing * self.min_spacing;
+
+        let mut placed: Vec<(Vec2, usize)> = Vec::new();
+        for _ in 0..attempts {
+            let (angle, distance) = (self.rng.next() * TAU, self.rng.next().sqrt() * dab.radius);
+            let loc = dab.loc.xz() + Vec2::from_angle(angle) * distance;
+            if self.rng.next() >= dab.weight(Vec3::new(loc.x, dab.loc.y, loc.y)) {
+                continue;
+            }
+            if taken.iter().any(|other| other.distance_squared(loc) < spacing) {
+                continue;
+            }
+            taken.push(loc);
+            placed.push((loc, (self.rng.next() * self.prefabs.len() as f32) as usize % self.prefabs.len()));
+        }
+        if placed.is_empty() {
+            return;
+        }
+
+        let mut state: SystemState<(MeshRayCast, Query<(), With<BrushSurface>>)> = SystemState::new(world);
+        let instances: Vec<ScatterInstance> = {
+            let (mut ray_cast, surfaces) = state.get_mut(world);
+            placed.into_iter().map(|(loc, prefab)| {
+                let (point, normal) = sample_surface(&mut ray_cast, &surfaces, loc)
+                    .unwrap_or((Vec3::new(loc.x, dab.loc.y, loc.y), Vec3::Y));
+                let yaw = Quat::from_rotation_y(self.rng.range(self.rotation));
+                let rotation = match self.align {
+                    true => Quat::from_rotation_arc(Vec3::Y, normal) * yaw,
+                    false => yaw
+                };
+                ScatterInstance::new(
+                    self.prefabs[prefab].clone(),
+                    Transform::from_translation(point)
+                        .with_rotation(rotation)
+                        .with_scale(Vec3::splat(self.rng.range(self.scale)))
+                )
+            }).collect()
+        };
+        for instance in instances {
+            instance.spawn(world);
+            self.spawned.push(instance);
+        }
+    }
+
+    fn erase(&mut self, world: &mut World, dab: &BrushDab) {
+        let under: Vec<(Entity, ScatterInstance)> = world
+            .query::<(Entity, &Scattered, &Transform)>()
+            .iter(world)
+            .filter(|(_, _, transform)| dab.weight(transform.translation) > 0.0)
+            .map(|(entity, scattered, transform)| (entity, ScatterInstance{id: scattered.id, prefab: scattered.prefab.clone(), transform: *transform}))
+            .collect();
+        for (entity, instance) in under {
+            if self.rng.next() >= dab.weight(instance.transform.translation) {
+                continue;
+            }
+            world.entity_mut(entity).despawn();
+            self.erased.push(instance);
+        }
+    }
+}
+
+impl BrushType for ScatterBrush {
+    fn name(&self) -> &str {
+        "Scatter"
+    }
+
+    fn params(&self) -> Vec<BrushParam> {
+        vec![
+            BrushParam::Slider{name: "density", value: self.density, min: 0.0, max: 1.0},
+            BrushParam::Slider{name: "min spacing", value: self.min_spacing, min: 0.0, max: 20.0},
+            BrushParam::Slider{name: "min scale", value: self.scale.x, min: 0.1, max: 5.0},
+            BrushParam::Slider{name: "max scale", value: self.scale.y, min: 0.1, max: 5.0},
+            BrushParam::Toggle{name: "align", value: self.align},
+            BrushParam::Toggle{name: "erase", value: self.erase}
+        ]
+    }
+
+    fn set_param(&mut self, name: &str, value: BrushParamValue) {
+        match (name, value) {
+            ("density", BrushParamValue::Float(v)) => self.density = v,
+            ("min spacing", BrushParamValue::Float(v)) => self.min_spacing = v,
+            ("min scale", BrushParamValue::Float(v)) => self.scale = Vec2::new(v, self.scale.y.max(v)),
+            ("max scale", BrushParamValue::Float(v)) => self.scale = Vec2::new(self.scale.x.min(v), v),
+            ("align", BrushParamValue::Bool(v)) => self.align = v,
+            ("erase", BrushParamValue::Bool(v)) => self.erase = v,
+            _ => {}
+        }
+    }
+
+    fn started(&mut self, _world: &mut World, stroke: &BrushStroke) {
+        self.rng = ScatterRng::for_stroke(self.seed, stroke.id);
+        self.spawned.clear();
+        self.erased.clear();
+    }
+
+    // Shift flips between scattering and erasing
+    fn apply(&mut self, world: &mut World, dab: &BrushDab, stroke: &BrushStroke) {
+        match self.erase != stroke.invert() {
+            true => self.erase(world, dab),
+            false => self.scatter(world, dab)
+        }
+    }
+
+    fn done(&mut self, _world: &mut World, _stroke: &BrushStroke) -> Option<Box<dyn Change>> {
+        if self.spawned.is_empty() && self.erased.is_empty() {
+            return None;
+        }
+        let change = ChangeScatter {
+            spawned: std::mem::take(&mut self.spawned),
+            erased: std::mem::take(&mut self.erased)
+        };
+        Some(Box::new(change))
+    }
+}
+
+
+// Instances spawned and erased during one stroke, found again by their Scattered id
+#[derive(Clone)]
+pub struct ChangeScatter {
+    spawned: Vec<ScatterInstance>,
+    erased:  Vec<ScatterInstance>
+}
+
+fn despawn_instances(world: &mut World, instances: &[ScatterInstance]) {
+    let entities: Vec<Entity> = world
+        .query::<(Entity, &Scattered)>()
+        .iter(world)
+        .filter(|(_, scattered)| instances.iter().any(|instance| instance.id == scattered.id))
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in entities {
+        world.entity_mut(entity).despawn();
+    }
+}
+
+fn respawn_instances(world: &mut World, instances: &[ScatterInstance]) {
+    for instance in instances.iter() {
+        instance.spawn(world);
+    }
+}
+
+impl Change for ChangeScatter {
+    fn undo(
+        &mut self,
+        world:      &mut World
+    ) {
+        despawn_instances(world, &self.spawned);
+        respawn_instances(world, &self.erased);
+    }
+
+    fn redo(
+        &mut self,
+        world: &mut World
+    ) {
+        respawn_instances(world, &self.spawned);
+        despawn_instances(world, &self.erased);
+    }
+
+    fn record(
+        &self,
+        changes: &mut ResMut<Changes>
+    ) {
+        changes.record(Box::new(self.clone()));
+    }
+}
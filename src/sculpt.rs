@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use bevy::camera::primitives::Aabb;
+use bevy::mesh::{PrimitiveTopology, VertexAttributeValues};
+use bevy::prelude::*;
+
+use crate::brushes::{BrushDab, BrushParam, BrushParamValue, BrushStroke, BrushSurface, BrushType};
+use crate::tracker::{Change, Changes};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum SculptOp {
+    Raise,
+    Lower,
+    Flatten(Option<f32>),               // Target height, the height under the first dab if None
+    Smooth,
+    Noise{frequency: f32, seed: u32}
+}
+
+// Edits the heights of the BrushSurface meshes, which need to keep their data in the main world
+#[derive(Clone)]
+pub struct SculptBrush {
+    pub op:     SculptOp,
+    pub rate:   f32,                    // Height per second at full weight
+    target:     Option<f32>,
+    edited:     HashMap<AssetId<Mesh>, SculptedMesh>
+}
+
+#[derive(Clone)]
+struct SculptedMesh {
+    handle:     Handle<Mesh>,
+    before:     Vec<[f32; 3]>,
+    topology:   MeshTopology
+}
+
+// Triangles around and vertices next to each vertex, built once per mesh and stroke
+#[derive(Clone, Default)]
+struct MeshTopology {
+    triangles:  Vec<[usize; 3]>,
+    around:     Vec<Vec<usize>>,        // Indices into triangles
+    neighbours: Vec<Vec<usize>>
+}
+
+impl SculptBrush {
+    pub fn new(op: SculptOp) -> Self {
+        SculptBrush {
+            op,
+            rate: 5.0,
+            target: None,
+            edited: HashMap::new()
+        }
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    // Ctrl smooths whatever the operation, Shift swaps raise and lower and flips the noise
+    fn stroke_op(&self, stroke: &BrushStroke) -> SculptOp {
+        if stroke.smooth() {
+            return SculptOp::Smooth;
+        }
+        match (self.op, stroke.invert()) {
+            (SculptOp::Raise, true) => SculptOp::Lower,
+            (SculptOp::Lower, true) => SculptOp::Raise,
+            (op, _) => op
+        }
+    }
+
+    // New local positions of the vertices moved by the dab
+    fn sculpt(
+        &self,
+        stroke:     &BrushStroke,
+        positions:  &[[f32; 3]],
+        neighbours: &[Vec<usize>],
+        transform:  &GlobalTransform,
+        dab:        &BrushDab,
+        target:     f32
+    ) -> Vec<(usize, [f32; 3])> {
+        let world = |i: usize| transform.transform_point(Vec3::from(positions[i]));
+        let inverse = transform.affine().inverse();
+        let amount = self.rate * dab.elapsed;
+        let op = self.stroke_op(stroke);
+        let mut moved = Vec::new();
+
+        for i in 0..positions.len() {
+            let point = world(i);
+            let weight = dab.weight(point);
+            if weight <= 0.0 {
+                continue;
+            }
+            let step = weight * amount;
+            let height = match op {
+                SculptOp::Raise => point.y + step,
+                SculptOp::Lower => point.y - step,
+                SculptOp::Flatten(_) => point.y + (target - point.y) * step.min(1.0),
+                SculptOp::Smooth => {
+                    let Some(near) = neighbours.get(i).filter(|near| !near.is_empty()) else {continue;};
+                    let average = near.iter().map(|n| world(*n).y).sum::<f32>() / near.len() as f32;
+                    point.y + (average - point.y) * step.min(1.0)
+                }
+                SculptOp::Noise{frequency, seed} => {
+                    let sign = if stroke.invert() {-1.0} else {1.0};
+                    point.y + sign * value_noise(point.xz() * frequency, seed) * step
+                }
+            };
+            if height != point.y {
+                moved.push((i, inverse.transform_point3(point.with_y(height)).to_array()));
+            }
+        }
+        moved
+    }
+}
+
+const SCULPT_OPS: [&str; 5] = ["Raise", "Lower", "Flatten", "Smooth", "Noise"];
+
+impl BrushType for SculptBrush {
+    fn name(&self) -> &str {
+        "Sculpt"
+    }
+
+    fn params(&self) -> Vec<BrushParam> {
+        let op = match self.op {
+            SculptOp::Raise => 0,
+            SculptOp::Lower => 1,
+            SculptOp::Flatten(_) => 2,
+            SculptOp::Smooth => 3,
+            SculptOp::Noise{..} => 4
+        };
+        let mut params = vec![
+            BrushParam::Dropdown{name: "operation", value: op, options: SCULPT_OPS.to_vec()},
+            BrushParam::Slider{name: "rate", value: self.rate, min: 0.0, max: 20.0}
+        ];
+        if let SculptOp::Noise{frequency, ..} = self.op {
+            params.push(BrushParam::Slider{name: "frequency", value: frequency, min: 0.01, max: 1.0});
+        }
+        params
+    }
+
+    fn set_param(&mut self, name: &str, value: BrushParamValue) {
+        match (name, value) {
+            ("operation", BrushParamValue::Choice(i)) => {
+                self.op = match i {
+                    0 => SculptOp::Raise,
+                    1 => SculptOp::Lower,
+                    2 => SculptOp::Flatten(None),
+                    3 => SculptOp::Smooth,
+                    _ if matches!(self.op, SculptOp::Noise{..}) => return,
+                    _ => SculptOp::Noise{frequency: 0.1, seed: 0}
+                };
+            }
+            ("rate", BrushParamValue::Float(v)) => self.rate = v,
+            ("frequency", BrushParamValue::Float(v)) => {
+                if let SculptOp::Noise{frequency, ..} = &mut self.op {
+                    *frequency = v;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn started(&mut self, _world: &mut World, _stroke: &BrushStroke) {
+        self.target = None;
+        self.edited.clear();
+    }
+
+    fn apply(&mut self, world: &mut World, dab: &BrushDab, stroke: &BrushStroke) {
+        let op = self.stroke_op(stroke);
+        let target = match op {
+            SculptOp::Flatten(Some(height)) => height,
+            _ => *self.target.get_or_insert(dab.loc.y)
+        };
+        let mut query = world.query_filtered::<(Entity, &Mesh3d, &GlobalTransform, Option<&Aabb>), With<BrushSurface>>();
+        let surfaces: Vec<(Entity, Handle<Mesh>, GlobalTransform)> = query
+            .iter(world)
+            .filter(|(_, _, transform, aabb)| aabb.is_none_or(|aabb| dab_touches(dab, aabb, transform)))
+            .map(|(entity, mesh, transform, _)| (entity, mesh.0.clone(), *transform))
+            .collect();
+
+        // Local bounds of the moved vertices for each entity
+        let mut touched: Vec<(Entity, Vec3, Vec3)> = Vec::new();
+        world.resource_scope(|_world: &mut World, mut meshes: Mut<Assets<Mesh>>| {
+            for (entity, handle, transform) in surfaces.iter() {
+                let Some(mesh) = meshes.get_mut(handle) else {continue;};
+                if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+                    continue;
+                }
+                let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {continue;};
+                self.edited.entry(handle.id()).or_insert_with(|| SculptedMesh {
+                    handle:   handle.clone(),
+                    before:   positions.clone(),
+                    topology: MeshTopology::new(mesh)
+                });
+                let Some(sculpted) = self.edited.get(&handle.id()) else {continue;};
+                let moved = self.sculpt(stroke, positions, &sculpted.topology.neighbours, transform, dab, target);
+                if moved.is_empty() {
+                    continue;
+                }
+                let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION) else {continue;};
+                let (mut min, mut max) = (Vec3::MAX, Vec3::MIN);
+                for (i, position) in moved.iter() {
+                    positions[*i] = *position;
+                    min = min.min(Vec3::from(*position));
+                    max = max.max(Vec3::from(*position));
+                }
+                let moved: Vec<usize> = moved.into_iter().map(|(i, _)| i).collect();
+                sculpted.topology.update_normals(mesh, &moved);
+                touched.push((*entity, min, max));
+            }
+        });
+
+        // Grown rather than recalculated, the bounds only tighten again after undo or redo
+        for (entity, min, max) in touched {
+            if let Some(mut aabb) = world.get_mut::<Aabb>(entity) {
+                *aabb = Aabb::from_min_max(Vec3::from(aabb.min()).min(min), Vec3::from(aabb.max()).max(max));
+            }
+        }
+    }
+
+    fn done(&mut self, world: &mut World, _stroke: &BrushStroke) -> Option<Box<dyn Change>> {
+        let mut change = ChangeSculpt{meshes: Vec::new()};
+        if let Some(meshes) = world.get_resource::<Assets<Mesh>>() {
+            for sculpted in self.edited.values() {
+                let Some(after) = meshes.get(&sculpted.handle).and_then(mesh_positions) else {continue;};
+                if after != sculpted.before {
+                    change.meshes.push(MeshPositions{handle: sculpted.handle.clone(), before: sculpted.before.clone(), after});
+                }
+            }
+        }
+        self.edited.clear();
+
+        match change.meshes.is_empty() {
+            true => None,
+            false => Some(Box::new(change))
+        }
+    }
+}
+
+
+// Vertex positions of the sculpted meshes before and after the stroke
+#[derive(Clone)]
+pub struct ChangeSculpt {
+    meshes: Vec<MeshPositions>
+}
+
+#[derive(Clone)]
+struct MeshPositions {
+    handle: Handle<Mesh>,
+    before: Vec<[f32; 3]>,
+    after:  Vec<[f32; 3]>
+}
+
+impl ChangeSculpt {
+    fn restore(&self, world: &mut World, after: bool) {
+        if let Some(mut meshes) = world.get_resource_mut::<Assets<Mesh>>() {
+            for positions in self.meshes.iter() {
+                let Some(mesh) = meshes.get_mut(&positions.handle) else {continue;};
+                set_mesh_positions(mesh, if after {positions.after.clone()} else {positions.before.clone()});
+            }
+        }
+        let mut query = world.query::<(Entity, &Mesh3d)>();
+        let touched: Vec<Entity> = query
+            .iter(world)
+            .filter(|(_, mesh)| self.meshes.iter().any(|positions| positions.handle.id() == mesh.id()))
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in touched {
+            world.entity_mut(entity).remove::<Aabb>();
+        }
+    }
+}
+
+impl Change for ChangeSculpt {
+    fn undo(
+        &mut self,
+        world:      &mut World
+    ) {
+        self.restore(world, false);
+    }
+
+    fn redo(
+        &mut self,
+        world: &mut World
+    ) {
+        self.restore(world, true);
+    }
+
+    fn record(
+        &self,
+        changes: &mut ResMut<Changes>
+    ) {
+        changes.record(Box::new(self.clone()));
+    }
+}
+
+
+fn mesh_positions(mesh: &Mesh) -> Option<Vec<[f32; 3]>> {
+    match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float32x3(positions) => Some(positions.clone()),
+        _ => None
+    }
+}
+
+fn set_mesh_positions(mesh: &mut Mesh, positions: Vec<[f32; 3]>) {
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    if mesh.primitive_topology() == PrimitiveTopology::TriangleList {
+        mesh.compute_normals();
+    }
+}
+
+impl MeshTopology {
+    fn new(mesh: &Mesh) -> Self {
+        let count = mesh.count_vertices();
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..count).collect()
+        };
+        let mut topology = MeshTopology {
+            triangles:  Vec::new(),
+            around:     vec![Vec::new(); count],
+            neighbours: vec![Vec::new(); count]
+        };
+        for triangle in indices.chunks_exact(3) {
+            let triangle = [triangle[0], triangle[1], triangle[2]];
+            if triangle.iter().any(|i| *i >= count) {
+                continue;
+            }
+            for i in triangle {
+                topology.around[i].push(topology.triangles.len());
+            }
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                let (a, b) = (triangle[a], triangle[b]);
+                if !topology.neighbours[a].contains(&b) {
+                    topology.neighbours[a].push(b);
+                }
+                if !topology.neighbours[b].contains(&a) {
+                    topology.neighbours[b].push(a);
+                }
+            }
+            topology.triangles.push(triangle);
+        }
+        topology
+    }
+
+    // Area weighted normals of the moved vertices and the ones next to them
+    fn update_normals(&self, mesh: &mut Mesh, moved: &[usize]) {
+        let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {return;};
+        let mut vertices: Vec<usize> = moved.to_vec();
+        for i in moved {
+            vertices.extend(self.neighbours[*i].iter().copied());
+        }
+        vertices.sort_unstable();
+        vertices.dedup();
+        let normals: Vec<(usize, [f32; 3])> = vertices.into_iter().map(|i| {
+            let normal: Vec3 = self.around[i].iter().map(|t| {
+                let [a, b, c] = self.triangles[*t].map(|v| Vec3::from(positions[v]));
+                (b - a).cross(c - a)
+            }).sum();
+            (i, normal.normalize_or(Vec3::Y).to_array())
+        }).collect();
+
+        let Some(VertexAttributeValues::Float32x3(mesh_normals)) = mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL) else {return;};
+        for (i, normal) in normals {
+            if let Some(mesh_normal) = mesh_normals.get_mut(i) {
+                *mesh_normal = normal;
+            }
+        }
+    }
+}
+
+// Dab circle against the world bounds of the surface on XZ
+fn dab_touches(dab: &BrushDab, aabb: &Aabb, transform: &GlobalTransform) -> bool {
+    let affine = transform.affine();
+    let center = affine.transform_point3a(aabb.center);
+    let half = Mat3A::from_cols(affine.matrix3.x_axis.abs(), affine.matrix3.y_axis.abs(), affine.matrix3.z_axis.abs()) * aabb.half_extents;
+    let (min, max) = ((center - half).xz(), (center + half).xz());
+    dab.loc.xz().clamp(min, max).distance(dab.loc.xz()) < dab.radius
+}
+
+// Smoothed value noise in -1..1
+fn value_noise(loc: Vec2, seed: u32) -> f32 {
+    let cell = loc.floor();
+    let f = loc - cell;
+    let u = f * f * (Vec2::splat(3.0) - 2.0 * f);
+    let (x, z) = (cell.x as i32, cell.y as i32);
+    let a = lattice(x, z, seed).lerp(lattice(x + 1, z, seed), u.x);
+    let b = lattice(x, z + 1, seed).lerp(lattice(x + 1, z + 1, seed), u.x);
+    a.lerp(b, u.y)
+}
+
+fn lattice(x: i32, z: i32, seed: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x27d4_eb2d)
+        ^ (z as u32).wrapping_mul(0x1656_67b1)
+        ^ seed.wrapping_mul(0x9e37_79b9);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    (h & 0xffff) as f32 / 32767.5 - 1.0
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brushes::BrushFalloff;
+    use bevy::camera::primitives::MeshAabb;
+
+    // 5x5 vertices one unit apart, centered on the origin
+    fn grid() -> Mesh {
+        Plane3d::default().mesh().size(4.0, 4.0).subdivisions(3).build()
+    }
+
+    fn dab(loc: Vec3, falloff: BrushFalloff) -> BrushDab {
+        BrushDab{elapsed: 0.1, loc, radius: 1.5, strength: 1.0, hardness: 0.0, falloff}
+    }
+
+    fn positions(mesh: &Mesh) -> Vec<[f32; 3]> {
+        mesh_positions(mesh).unwrap()
+    }
+
+    fn height(moved: &[(usize, [f32; 3])], positions: &[[f32; 3]], x: f32, z: f32) -> f32 {
+        let i = positions.iter().position(|p| p[0] == x && p[2] == z).unwrap();
+        moved.iter().find(|(j, _)| *j == i).map(|(_, p)| p[1]).unwrap_or(positions[i][1])
+    }
+
+    #[test]
+    fn falloff_weights() {
+        assert_eq!(BrushFalloff::Linear.weight(1.0, 2.0, 0.0, 1.0), 0.5);
+        assert_eq!(BrushFalloff::Linear.weight(2.0, 2.0, 0.0, 1.0), 0.0);
+        assert_eq!(BrushFalloff::Smooth.weight(0.5, 2.0, 0.5, 0.8), 0.8);
+        assert_eq!(BrushFalloff::Smooth.weight(1.5, 2.0, 0.5, 1.0), 0.5);
+        assert_eq!(BrushFalloff::Constant.weight(1.9, 2.0, 0.0, 0.3), 0.3);
+    }
+
+    #[test]
+    fn raise_and_lower() {
+        let mesh = grid();
+        let positions = positions(&mesh);
+        let topology = MeshTopology::new(&mesh);
+        let brush = SculptBrush::new(SculptOp::Raise);
+        let mut stroke = BrushStroke::new(Vec3::ZERO);
+        let dab = dab(Vec3::ZERO, BrushFalloff::Constant);
+
+        // Only the 3x3 vertices inside the radius move, by rate * elapsed
+        let moved = brush.sculpt(&stroke, &positions, &topology.neighbours, &GlobalTransform::IDENTITY, &dab, 0.0);
+        assert_eq!(moved.len(), 9);
+        assert_eq!(height(&moved, &positions, 0.0, 0.0), 0.5);
+        assert_eq!(height(&moved, &positions, 1.0, 1.0), 0.5);
+        assert_eq!(height(&moved, &positions, 2.0, 0.0), 0.0);
+
+        stroke.shift = true;
+        let moved = brush.sculpt(&stroke, &positions, &topology.neighbours, &GlobalTransform::IDENTITY, &dab, 0.0);
+        assert_eq!(height(&moved, &positions, 0.0, 0.0), -0.5);
+    }
+
+    #[test]
+    fn flatten_and_smooth() {
+        let mut mesh = grid();
+        let topology = MeshTopology::new(&mesh);
+        let mut positions = positions(&mesh);
+        let center = positions.iter().position(|p| p[0] == 0.0 && p[2] == 0.0).unwrap();
+        positions[center][1] = 2.0;
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
+        let stroke = BrushStroke::new(Vec3::ZERO);
+        let dab = dab(Vec3::ZERO, BrushFalloff::Constant);
+
+        // Half way towards the target with a step of 0.5
+        let brush = SculptBrush::new(SculptOp::Flatten(Some(1.0)));
+        let moved = brush.sculpt(&stroke, &positions, &topology.neighbours, &GlobalTransform::IDENTITY, &dab, 1.0);
+        assert_eq!(height(&moved, &positions, 0.0, 0.0), 1.5);
+        assert_eq!(height(&moved, &positions, 1.0, 0.0), 0.5);
+
+        // Half way towards the flat neighbours
+        let brush = SculptBrush::new(SculptOp::Smooth);
+        let moved = brush.sculpt(&stroke, &positions, &topology.neighbours, &GlobalTransform::IDENTITY, &dab, 0.0);
+        assert_eq!(height(&moved, &positions, 0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn apply_in_place() {
+        let mut world = World::new();
+        let mut meshes = Assets::<Mesh>::default();
+        let mesh = grid();
+        let aabb = mesh.compute_aabb().unwrap();
+        let handle = meshes.add(mesh);
+        world.insert_resource(meshes);
+        let near = world.spawn((BrushSurface, Mesh3d(handle.clone()), GlobalTransform::IDENTITY, aabb)).id();
+        let far_handle = world.resource_mut::<Assets<Mesh>>().add(grid());
+        let far = GlobalTransform::from_translation(Vec3::new(100.0, 0.0, 0.0));
+        world.spawn((BrushSurface, Mesh3d(far_handle.clone()), far, aabb));
+
+        let mut brush = SculptBrush::new(SculptOp::Raise);
+        let stroke = BrushStroke::new(Vec3::ZERO);
+        brush.started(&mut world, &stroke);
+        brush.apply(&mut world, &dab(Vec3::new(2.0, 0.0, 0.0), BrushFalloff::Constant), &stroke);
+
+        // The far surface is outside the dab and never recorded
+        assert_eq!(brush.edited.len(), 1);
+        let mesh = world.resource::<Assets<Mesh>>().get(&handle).unwrap();
+        let positions = positions(mesh);
+        assert_eq!(height(&[], &positions, 2.0, 0.0), 0.5);
+        assert_eq!(height(&[], &positions, 0.0, 0.0), 0.0);
+
+        // Normals next to the raised edge tilt away from it, the rest stay up
+        let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL) else {panic!()};
+        let at = |x: f32, z: f32| Vec3::from(normals[positions.iter().position(|p| p[0] == x && p[2] == z).unwrap()]);
+        assert!(at(0.0, 0.0).x < 0.0);
+        assert_eq!(at(-2.0, 0.0), Vec3::Y);
+        assert_eq!(world.get::<Aabb>(near).unwrap().max().y, 0.5);
+
+        let change = brush.done(&mut world, &stroke);
+        assert!(change.is_some());
+    }
+}
@@ -0,0 +1,314 @@
+use bevy::camera::primitives::Aabb;
+use bevy::picking::mesh_picking::ray_cast::{MeshRayCast, MeshRayCastSettings};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_enhanced_input::prelude::Press;
+use bevy_enhanced_input::prelude::*;
+
+use crate::box_select::{BoxSelect, BoxSelectFinal, BoxSelectPreview, BoxSelectSettings};
+use crate::circle_select::CircleSelectFinal;
+use crate::lasso_select::PolygonSelectFinal;
+use crate::tracker::{Change, Changes};
+use crate::world_pos::{EditorCamera, UiHover};
+
+
+// For bindings shared between tools (chord mouse buttons, Escape), so one tool doesn't swallow them
+pub(crate) const PASS_THROUGH: ActionSettings = ActionSettings {
+    accumulation:   Accumulation::Cumulative,
+    require_reset:  false,
+    consume_input:  false
+};
+
+// Key member of another tool's chord, picking is skipped while one is held
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub(crate) struct ToolKey;
+
+
+pub struct PGEditorSelectionPlugin;
+
+impl Plugin for PGEditorSelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<Selection>()
+        .add_input_context::<PickSelectController>()
+        .add_observer(pick_select)
+        .add_observer(select_box)
+        .add_observer(select_circle)
+        .add_observer(select_polygon)
+        .add_observer(preview_box)
+        .add_systems(Update, clear_preview.run_if(not(any_with_component::<BoxSelect>)))
+        ;
+    }
+}
+
+// Only entities marked Selectable end up in the Selection
+#[derive(Component, Default, Reflect)]
+pub struct Selectable;
+
+// Selectable entities inside the box that is currently dragged
+#[derive(Component, Default, Reflect)]
+pub struct PreviewSelected;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum SelectMode {
+    #[default]
+    Replace,
+    Add,
+    Toggle
+}
+
+impl SelectMode {
+    // Shift adds, Ctrl toggles
+    pub fn from_keys(keys: &ButtonInput<KeyCode>) -> Self {
+        if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+            SelectMode::Toggle
+        } else if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            SelectMode::Add
+        } else {
+            SelectMode::Replace
+        }
+    }
+}
+
+
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    entities: Vec<Entity>
+}
+
+impl Selection {
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn add(&mut self, entity: Entity){
+        if !self.contains(entity){
+            self.entities.push(entity);
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity){
+        self.entities.retain(|e| *e != entity);
+    }
+
+    pub fn toggle(&mut self, entity: Entity){
+        if self.contains(entity){
+            self.remove(entity);
+        } else {
+            self.add(entity);
+        }
+    }
+
+    pub fn clear(&mut self){
+        self.entities.clear();
+    }
+
+    pub fn apply(&mut self, mode: SelectMode, entities: impl IntoIterator<Item = Entity>){
+        if mode == SelectMode::Replace {
+            self.clear();
+        }
+        for entity in entities {
+            match mode {
+                SelectMode::Toggle => self.toggle(entity),
+                _ => self.add(entity)
+            }
+        }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct ChangeSelection {
+    pub old: Selection,
+    pub new: Selection
+}
+
+impl Change for ChangeSelection {
+    fn undo(
+        &mut self, 
+        world:      &mut World
+    ) {
+        *world.resource_mut::<Selection>() = self.old.clone();
+    }
+
+    fn redo(
+        &mut self, 
+        world: &mut World
+    ) {
+        *world.resource_mut::<Selection>() = self.new.clone();
+    }
+
+    fn record(
+        &self,
+        changes: &mut ResMut<Changes> 
+    ) {
+        changes.record(Box::new(self.clone()));
+    }
+}
+
+
+#[derive(Component, Reflect)]
+pub struct PickSelectController;
+
+pub fn pick_select_controller() -> impl Bundle {
+    return (
+        PickSelectController,
+        actions!(PickSelectController[
+            (Action::<PickSelect>::new(), Press::default(), bindings![MouseButton::Left])
+        ])
+    );
+}
+
+#[derive(InputAction)]
+#[action_output(bool)]
+struct PickSelect;
+
+
+// Walks up the hierarchy, so clicking a child mesh selects its Selectable parent
+pub(crate) fn selectable_root(
+    entity:     Entity,
+    selectable: &Query<(), With<Selectable>>,
+    parents:    &Query<&ChildOf>
+) -> Option<Entity> {
+    let mut current = entity;
+    loop {
+        if selectable.contains(current) {
+            return Some(current);
+        }
+        current = parents.get(current).ok()?.parent();
+    }
+}
+
+// Ray under the cursor, EditorCamera is preferred over other cameras
+pub(crate) fn cursor_ray(
+    window:  &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform, Has<EditorCamera>)>
+) -> Option<Ray3d> {
+    let cursor = window.single().ok()?.cursor_position()?;
+    let (camera, camera_transform, _) = cameras
+        .iter()
+        .find(|(_, _, is_editor)| *is_editor)
+        .or(cameras.iter().next())?;
+    camera.viewport_to_world(camera_transform, cursor).ok()
+}
+
+// Nearest Selectable entity hit by the ray
+pub(crate) fn pick_selectable(
+    ray:        Ray3d,
+    ray_cast:   &mut MeshRayCast,
+    selectable: &Query<(), With<Selectable>>,
+    parents:    &Query<&ChildOf>
+) -> Option<Entity> {
+    let filter = |entity: Entity| selectable_root(entity, selectable, parents).is_some();
+    let settings = MeshRayCastSettings::default().with_filter(&filter);
+    ray_cast
+        .cast_ray(ray, &settings)
+        .first()
+        .and_then(|(entity, _)| selectable_root(*entity, selectable, parents))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pick_select(
+    _trigger:       On<Start<PickSelect>>,
+    mut ray_cast:   MeshRayCast,
+    mut selection:  ResMut<Selection>,
+    keys:           Res<ButtonInput<KeyCode>>,
+    window:         Query<&Window, With<PrimaryWindow>>,
+    cameras:        Query<(&Camera, &GlobalTransform, Has<EditorCamera>)>,
+    selectable:     Query<(), With<Selectable>>,
+    parents:        Query<&ChildOf>,
+    tool_keys:      Query<&ActionState, With<ToolKey>>,
+    ui_hover:       UiHover
+){
+    // The click belongs to the UI or to the brush, lasso, polygon, circle or box tool
+    if ui_hover.any() || tool_keys.iter().any(|state| *state == ActionState::Fired) {
+        return;
+    }
+    let Some(ray) = cursor_ray(&window, &cameras) else {return;};
+    let picked = pick_selectable(ray, &mut ray_cast, &selectable, &parents);
+
+    let mode = SelectMode::from_keys(&keys);
+    match picked {
+        Some(entity) => selection.apply(mode, [entity]),
+        None if mode == SelectMode::Replace => selection.clear(),
+        None => {}
+    }
+}
+
+fn select_box(
+    trigger:        On<BoxSelectFinal>,
+    mut selection:  ResMut<Selection>,
+    keys:           Res<ButtonInput<KeyCode>>,
+    settings:       Option<Res<BoxSelectSettings>>,
+    changes:        Option<ResMut<Changes>>,
+    query:          Query<(Entity, &GlobalTransform, Option<&Aabb>), With<Selectable>>
+){
+    let settings = settings.map(|settings| settings.clone()).unwrap_or_default();
+    let entities = trigger.event().query_bounds(settings.policy, &query);
+    let old = selection.clone();
+    selection.apply(SelectMode::from_keys(&keys), entities);
+
+    if let Some(mut changes) = changes && settings.record_changes && *selection != old {
+        ChangeSelection{old, new: selection.clone()}.record(&mut changes);
+    }
+}
+
+fn select_circle(
+    trigger:        On<CircleSelectFinal>,
+    mut selection:  ResMut<Selection>,
+    keys:           Res<ButtonInput<KeyCode>>,
+    query:          Query<(Entity, &GlobalTransform), With<Selectable>>
+){
+    let entities = trigger.event().query_entities(&query);
+    selection.apply(SelectMode::from_keys(&keys), entities);
+}
+
+fn select_polygon(
+    trigger:        On<PolygonSelectFinal>,
+    mut selection:  ResMut<Selection>,
+    keys:           Res<ButtonInput<KeyCode>>,
+    query:          Query<(Entity, &GlobalTransform), With<Selectable>>
+){
+    let entities = trigger.event().query_entities(&query);
+    selection.apply(SelectMode::from_keys(&keys), entities);
+}
+
+fn preview_box(
+    trigger:        On<BoxSelectPreview>,
+    mut commands:   Commands,
+    settings:       Option<Res<BoxSelectSettings>>,
+    query:          Query<(Entity, &GlobalTransform, Option<&Aabb>), With<Selectable>>,
+    previews:       Query<(), With<PreviewSelected>>
+){
+    let policy = settings.map(|settings| settings.policy).unwrap_or_default();
+    for (entity, transform, aabb) in query.iter(){
+        let selected = trigger.event().selects(policy, transform, aabb);
+        let previewed = previews.contains(entity);
+        if selected && !previewed {
+            commands.entity(entity).insert(PreviewSelected);
+        } else if !selected && previewed {
+            commands.entity(entity).remove::<PreviewSelected>();
+        }
+    }
+}
+
+fn clear_preview(
+    mut commands:   Commands,
+    query:          Query<Entity, With<PreviewSelected>>
+){
+    for entity in query.iter(){
+        commands.entity(entity).remove::<PreviewSelected>();
+    }
+}
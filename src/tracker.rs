@@ -98,7 +98,9 @@ impl Changes {
         self.data[index].redo(world);
     }
 
+    // Drops the undone changes, they can't be redone after a new one
     pub fn record(&mut self, change: Box<dyn Change>){
+        self.data.truncate(self.index as usize);
         self.data.push(change);
         self.index = self.len();
     }
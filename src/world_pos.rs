@@ -1,3 +1,6 @@
+use bevy::ecs::system::SystemParam;
+use bevy::picking::hover::HoverMap;
+use bevy::picking::pointer::PointerId;
 use bevy::prelude::*;
 
 // WorldPosition under cursor
@@ -22,4 +25,32 @@ impl WorldPos {
     pub fn get(&self) -> Option<Vec3> {
         self.loc
     }
+}
+
+// UI nodes under the mouse, the world tools ignore clicks that land on them
+#[derive(SystemParam)]
+pub struct UiHover<'w, 's> {
+    hover_map: Option<Res<'w, HoverMap>>,
+    nodes:     Query<'w, 's, (), With<Node>>
+}
+
+impl UiHover<'_, '_> {
+    pub fn any(&self) -> bool {
+        self.hover_map
+            .as_ref()
+            .and_then(|hover_map| hover_map.get(&PointerId::Mouse))
+            .is_some_and(|hits| hits.keys().any(|entity| self.nodes.contains(*entity)))
+    }
+}
+
+// Camera the editor tools orient themselves by
+#[derive(Component)]
+pub struct EditorCamera;
+
+impl EditorCamera {
+    // Rotation around Y that maps world X onto the camera's right vector
+    pub fn yaw(transform: &GlobalTransform) -> f32 {
+        let right = transform.right();
+        (-right.z).atan2(right.x)
+    }
 }
\ No newline at end of file